@@ -0,0 +1,139 @@
+use anyhow::{Context, Result};
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD;
+use jiff::SignedDuration;
+use jiff::tz::TimeZone;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::crypto::Smime;
+use crate::reply::Phrases;
+
+/// Connection settings, read from the environment at startup and again on
+/// every reconnect so rotated credentials are picked up.
+pub struct Credentials {
+    pub session_url: String,
+    pub username: String,
+    pub password: String,
+}
+
+impl Credentials {
+    /// Reads `JMAP_SESSION_URL`, `JMAP_USERNAME` and the password, preferring
+    /// `JMAP_PASSWORD_FILE` (re-read each time) over `JMAP_PASSWORD`.
+    pub fn from_env() -> Result<Self> {
+        let session_url =
+            std::env::var("JMAP_SESSION_URL").context("JMAP_SESSION_URL is required")?;
+        let username = std::env::var("JMAP_USERNAME").context("JMAP_USERNAME is required")?;
+
+        let password = match std::env::var("JMAP_PASSWORD_FILE") {
+            Ok(path) => std::fs::read_to_string(&path)
+                .with_context(|| format!("failed to read JMAP_PASSWORD_FILE {path}"))?
+                .trim_end_matches(['\r', '\n'])
+                .to_string(),
+            Err(_) => std::env::var("JMAP_PASSWORD")
+                .context("JMAP_PASSWORD or JMAP_PASSWORD_FILE is required")?,
+        };
+
+        Ok(Self {
+            session_url,
+            username,
+            password,
+        })
+    }
+    /// Values to scrub from logs: the password and the Basic credential
+    /// sent with every request.
+    pub fn secrets(&self) -> Vec<String> {
+        let basic = STANDARD.encode(format!("{}:{}", self.username, self.password));
+        vec![self.password.clone(), basic]
+    }
+}
+
+/// Operator settings that shape tool behaviour and output.
+pub struct Settings {
+    /// Zone used to render dates in summaries; `TIMEZONE` (an IANA name such
+    /// as "Europe/Berlin"), defaulting to the system zone.
+    pub timezone: TimeZone,
+    /// Directory for results too large to return inline (`SPOOL_DIR`).
+    /// Without it, every result is returned inline.
+    pub spool_dir: Option<PathBuf>,
+    /// Size in bytes above which results are spooled (`INLINE_RESULT_LIMIT`).
+    pub inline_limit: usize,
+    /// Command that decrypts PGP/MIME ciphertext from stdin to stdout
+    /// (`PGP_DECRYPT_COMMAND`, e.g. "gpg --batch --quiet --decrypt"). Split
+    /// on whitespace and run without a shell.
+    pub decrypt_command: Option<Vec<String>>,
+    /// Certificates for signing and encrypting outgoing mail.
+    pub smime: Smime,
+    /// Domains considered internal (`INTERNAL_DOMAINS`, comma-separated,
+    /// subdomains included). When set, sending to anyone else needs
+    /// `allow_external`.
+    pub internal_domains: Vec<String>,
+    /// How far back the Sent mailbox is checked for an identical message
+    /// before sending (`DUPLICATE_WINDOW_MINUTES`, default 10; 0 disables).
+    pub duplicate_window: Option<SignedDuration>,
+    /// Subject prefixes and quote header for replies and forwards.
+    pub phrases: Phrases,
+    /// Ask the user to approve every send and every permanent or bulk
+    /// delete through MCP elicitation (`CONFIRM_ACTIONS`). Calls are refused
+    /// when the client can't ask.
+    pub confirm_actions: bool,
+    /// How often subscribed mailbox resources are checked for changes when
+    /// the server's EventSource push is unavailable (`MAILBOX_POLL_SECONDS`,
+    /// default 30).
+    pub mailbox_poll_interval: Duration,
+}
+
+impl Settings {
+    pub fn from_env() -> Result<Self> {
+        let timezone = match std::env::var("TIMEZONE") {
+            Ok(name) => TimeZone::get(&name).with_context(|| format!("unknown TIMEZONE {name:?}"))?,
+            Err(_) => TimeZone::try_system().unwrap_or(TimeZone::UTC),
+        };
+        let spool_dir = std::env::var("SPOOL_DIR").ok().map(PathBuf::from);
+        let inline_limit = match std::env::var("INLINE_RESULT_LIMIT") {
+            Ok(v) => v.parse().context("INLINE_RESULT_LIMIT must be a number of bytes")?,
+            Err(_) => 100_000,
+        };
+        let decrypt_command = std::env::var("PGP_DECRYPT_COMMAND")
+            .ok()
+            .map(|c| c.split_whitespace().map(String::from).collect::<Vec<_>>())
+            .filter(|c| !c.is_empty());
+        let smime = Smime {
+            openssl: std::env::var("OPENSSL").unwrap_or_else(|_| "openssl".to_string()),
+            cert: std::env::var("SMIME_CERT").ok().map(PathBuf::from),
+            key: std::env::var("SMIME_KEY").ok().map(PathBuf::from),
+            cert_dir: std::env::var("SMIME_CERT_DIR").ok().map(PathBuf::from),
+        };
+        let internal_domains = std::env::var("INTERNAL_DOMAINS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|d| d.trim().trim_start_matches('@').to_ascii_lowercase())
+            .filter(|d| !d.is_empty())
+            .collect();
+        let duplicate_minutes: i64 = match std::env::var("DUPLICATE_WINDOW_MINUTES") {
+            Ok(v) => v.parse().context("DUPLICATE_WINDOW_MINUTES must be a number of minutes")?,
+            Err(_) => 10,
+        };
+        let duplicate_window =
+            (duplicate_minutes > 0).then(|| SignedDuration::from_mins(duplicate_minutes));
+        let poll_seconds: u64 = match std::env::var("MAILBOX_POLL_SECONDS") {
+            Ok(v) => v.parse().context("MAILBOX_POLL_SECONDS must be a number of seconds")?,
+            Err(_) => 30,
+        };
+        let confirm_actions = std::env::var("CONFIRM_ACTIONS")
+            .map(|v| matches!(v.as_str(), "1" | "true" | "yes"))
+            .unwrap_or(false);
+        Ok(Self {
+            timezone,
+            spool_dir,
+            inline_limit,
+            decrypt_command,
+            smime,
+            internal_domains,
+            duplicate_window,
+            phrases: Phrases::from_env(),
+            confirm_actions,
+            mailbox_poll_interval: Duration::from_secs(poll_seconds.max(1)),
+        })
+    }
+}
@@ -0,0 +1,36 @@
+use anyhow::{Context, Result, bail};
+use rmcp::model::{CreateElicitationRequestParam, ElicitationAction};
+use rmcp::{Peer, RoleServer};
+use serde_json::json;
+
+/// Asks the user, through MCP elicitation, to approve an action before it
+/// runs. Returns whether they accepted with the box ticked; fails when the
+/// client can't ask, so unconfirmed actions never go ahead silently.
+pub async fn ask(peer: &Peer<RoleServer>, message: String) -> Result<bool> {
+    if !peer.supports_elicitation() {
+        bail!(
+            "this server requires confirmation for sending and deleting, but the MCP client \
+             does not support elicitation"
+        );
+    }
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "confirm": {
+                "type": "boolean",
+                "title": "Go ahead",
+                "description": "Tick to carry out the action"
+            }
+        },
+        "required": ["confirm"]
+    });
+    let result = peer
+        .create_elicitation(CreateElicitationRequestParam {
+            message,
+            requested_schema: schema.as_object().cloned().unwrap_or_default(),
+        })
+        .await
+        .context("confirmation request failed")?;
+    let ticked = result.content.is_some_and(|c| c["confirm"] == true);
+    Ok(result.action == ElicitationAction::Accept && ticked)
+}
@@ -0,0 +1,113 @@
+use anyhow::{Context, Result, bail};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// How long an external crypto command may run, e.g. waiting on a locked
+/// agent.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Runs `command` with `input` on stdin and returns what it writes to
+/// stdout. Used for the operator's PGP decryption command, whose output
+/// for PGP/MIME is the inner MIME entity, and for openssl.
+pub async fn run(command: &[String], input: &[u8]) -> Result<Vec<u8>> {
+    let (program, args) = command.split_first().context("empty command")?;
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("failed to start {program}"))?;
+
+    let mut stdin = child.stdin.take().context("command has no stdin")?;
+    let input = input.to_vec();
+    let writer = tokio::spawn(async move {
+        stdin.write_all(&input).await?;
+        stdin.shutdown().await
+    });
+
+    let output = tokio::time::timeout(TIMEOUT, child.wait_with_output())
+        .await
+        .with_context(|| format!("{program} timed out after {}s", TIMEOUT.as_secs()))??;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("{program} failed ({}): {}", output.status, stderr.trim());
+    }
+    writer.await?.context("failed to write input")?;
+    Ok(output.stdout)
+}
+
+/// S/MIME material for outgoing mail, from `SMIME_CERT`, `SMIME_KEY` and
+/// `SMIME_CERT_DIR` (recipient certificates named `<address>.pem`).
+pub struct Smime {
+    pub openssl: String,
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    pub cert_dir: Option<PathBuf>,
+}
+
+impl Smime {
+    /// Wraps a MIME entity in a detached `multipart/signed` signature.
+    pub async fn sign(&self, entity: &[u8]) -> Result<Vec<u8>> {
+        let (Some(cert), Some(key)) = (&self.cert, &self.key) else {
+            bail!("signing requires SMIME_CERT and SMIME_KEY");
+        };
+        let command = vec![
+            self.openssl.clone(),
+            "smime".into(),
+            "-sign".into(),
+            "-signer".into(),
+            path_arg(cert),
+            "-inkey".into(),
+            path_arg(key),
+        ];
+        run(&command, entity).await
+    }
+
+    /// Encrypts a MIME entity to every recipient, plus the sender's own
+    /// certificate (when configured) so the Sent copy stays readable.
+    pub async fn encrypt(&self, entity: &[u8], recipients: &[String]) -> Result<Vec<u8>> {
+        let Some(dir) = &self.cert_dir else {
+            bail!("encryption requires SMIME_CERT_DIR");
+        };
+
+        let mut certs = Vec::new();
+        let mut missing = Vec::new();
+        for address in recipients {
+            let path = dir.join(format!("{}.pem", address.trim().to_ascii_lowercase()));
+            if path.is_file() {
+                certs.push(path_arg(&path));
+            } else {
+                missing.push(address.as_str());
+            }
+        }
+        if !missing.is_empty() {
+            bail!(
+                "no S/MIME certificate in {} for: {}",
+                dir.display(),
+                missing.join(", ")
+            );
+        }
+        if let Some(own) = &self.cert {
+            certs.push(path_arg(own));
+        }
+
+        let mut command = vec![
+            self.openssl.clone(),
+            "smime".into(),
+            "-encrypt".into(),
+            "-aes256".into(),
+        ];
+        command.extend(certs);
+        run(&command, entity).await
+    }
+}
+
+fn path_arg(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
@@ -0,0 +1,28 @@
+use jiff::tz::TimeZone;
+use jiff::{Timestamp, Zoned};
+
+/// Renders a JMAP UTC date (e.g. `receivedAt`) in `tz` with a relative
+/// label where it helps: "today 09:14", "yesterday 17:02", "Mon 09:14" for
+/// the past week, "3 Mar 09:14" this year, and "2023-03-01 09:14" before.
+/// Unparseable input is returned unchanged.
+pub fn friendly(utc: &str, tz: &TimeZone) -> String {
+    let Ok(ts) = utc.parse::<Timestamp>() else {
+        return utc.to_string();
+    };
+    let when = ts.to_zoned(tz.clone());
+    let now = Zoned::now().with_time_zone(tz.clone());
+    label(&when, &now)
+}
+
+fn label(when: &Zoned, now: &Zoned) -> String {
+    let days_ago = (now.date() - when.date()).get_days();
+    let time = when.strftime("%H:%M");
+
+    match days_ago {
+        0 => format!("today {time}"),
+        1 => format!("yesterday {time}"),
+        2..=6 => when.strftime("%a %H:%M").to_string(),
+        _ if when.year() == now.year() => when.strftime("%-d %b %H:%M").to_string(),
+        _ => when.strftime("%Y-%m-%d %H:%M").to_string(),
+    }
+}
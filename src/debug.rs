@@ -0,0 +1,173 @@
+use std::io::Write;
+use std::sync::RwLock;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::prelude::*;
+
+use crate::mcplog;
+
+/// Literal secret values (e.g. the JMAP password) scrubbed from every line.
+static SECRETS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+/// Key names whose values are credentials, in `key=value`, `key: value` or
+/// `"key": "value"` form. They match the end of a key, so `accessToken`
+/// counts as a token.
+const SECRET_KEYS: &[&str] = &["password", "passwd", "token", "secret", "authorization"];
+
+/// What may separate a secret key from its value.
+const SEPARATORS: &[&str] = &["=", ": ", ":", "\": \"", "\":\""];
+
+/// Authentication schemes that may lead an Authorization value; the
+/// credential is the token after them.
+const AUTH_SCHEMES: &[&str] = &["basic ", "bearer "];
+
+/// Registers the secrets to scrub and installs the tracing subscriber: a
+/// layer that forwards events to the MCP client once it sets a log level
+/// and, when `STALWART_MCP_DEBUG` is set, a verbose stderr log. Every line
+/// passes through [`redact`] before it is written, so the output is safe
+/// to paste into bug reports.
+pub fn init(secrets: Vec<String>) {
+    for secret in secrets {
+        add_secret(secret);
+    }
+
+    let enabled = std::env::var("STALWART_MCP_DEBUG")
+        .map(|v| matches!(v.as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false);
+
+    // stdout carries the MCP transport, so diagnostics must go to stderr.
+    let stderr = enabled.then(|| {
+        tracing_subscriber::fmt::layer()
+            .with_ansi(false)
+            .with_writer(RedactingStderr)
+            .with_filter(LevelFilter::DEBUG)
+    });
+    tracing_subscriber::registry().with(mcplog::layer()).with(stderr).init();
+}
+
+/// Registers another literal value to scrub, e.g. a rotated password.
+pub fn add_secret(secret: String) {
+    let mut secrets = SECRETS.write().unwrap();
+    if !secret.is_empty() && !secrets.contains(&secret) {
+        secrets.push(secret);
+    }
+}
+
+/// Replaces credentials in `line` with `[redacted]`.
+pub fn redact(line: &str) -> String {
+    let mut out = line.to_string();
+
+    for secret in SECRETS.read().unwrap().iter() {
+        out = out.replace(secret.as_str(), "[redacted]");
+    }
+    for key in SECRET_KEYS {
+        for separator in SEPARATORS {
+            out = mask_after(&out, &format!("{key}{separator}"));
+        }
+    }
+
+    out
+}
+
+/// Masks the token following each case-insensitive occurrence of `marker`,
+/// after an authentication scheme if one comes first.
+fn mask_after(line: &str, marker: &str) -> String {
+    let lower = line.to_ascii_lowercase();
+    let mut out = String::with_capacity(line.len());
+    let mut pos = 0;
+
+    while let Some(found) = lower[pos..].find(marker) {
+        let mut start = pos + found + marker.len();
+        if let Some(scheme) = AUTH_SCHEMES.iter().find(|s| lower[start..].starts_with(*s)) {
+            start += scheme.len();
+        }
+        out.push_str(&line[pos..start]);
+
+        let end = line[start..]
+            .find(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | ',' | '&' | ';'))
+            .map(|i| start + i)
+            .unwrap_or(line.len());
+        if end > start {
+            out.push_str("[redacted]");
+        }
+        pos = end;
+    }
+
+    out.push_str(&line[pos..]);
+    out
+}
+
+struct RedactingStderr;
+
+impl<'a> MakeWriter<'a> for RedactingStderr {
+    type Writer = RedactingLine;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingLine(Vec::new())
+    }
+}
+
+/// Buffers one formatted event and writes it, redacted, when dropped.
+struct RedactingLine(Vec<u8>);
+
+impl Write for RedactingLine {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for RedactingLine {
+    fn drop(&mut self) {
+        let line = redact(&String::from_utf8_lossy(&self.0));
+        let _ = std::io::stderr().lock().write_all(line.as_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn masks_authorization_headers() {
+        assert_eq!(
+            redact("authorization: Basic dXNlcjpwYXNz"),
+            "authorization: Basic [redacted]"
+        );
+        assert_eq!(
+            redact("headers: {\"Authorization\": \"Bearer eyJ.abc.def\"}"),
+            "headers: {\"Authorization\": \"Bearer [redacted]\"}"
+        );
+        assert_eq!(redact("AUTHORIZATION: BEARER abc123 ok"), "AUTHORIZATION: BEARER [redacted] ok");
+        assert_eq!(redact("Authorization=xyz"), "Authorization=[redacted]");
+    }
+
+    #[test]
+    fn masks_secret_keys_in_json_and_query_strings() {
+        assert_eq!(
+            redact(r#"{"username":"me","password":"hunter2","accessToken":"t0k"}"#),
+            r#"{"username":"me","password":"[redacted]","accessToken":"[redacted]"}"#
+        );
+        assert_eq!(redact("GET /x?Token=abc&page=2"), "GET /x?Token=[redacted]&page=2");
+        assert_eq!(redact("PASSWORD: s3cret, next"), "PASSWORD: [redacted], next");
+    }
+
+    #[test]
+    fn masks_registered_secrets_anywhere() {
+        add_secret("Zq9-literal-Xv".to_string());
+        assert_eq!(
+            redact("login with Zq9-literal-Xv failed"),
+            "login with [redacted] failed"
+        );
+    }
+
+    #[test]
+    fn leaves_ordinary_prose_alone() {
+        let prose = "basic setup done; bearer of news; token ring, secret garden";
+        assert_eq!(redact(prose), prose);
+    }
+}
@@ -0,0 +1,121 @@
+/// One per-recipient block of a delivery status notification (RFC 3464).
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub recipient: String,
+    pub action: String,
+    pub status: String,
+    pub diagnostic: Option<String>,
+}
+
+impl Report {
+    /// A permanent failure: the address should not be mailed again.
+    pub fn is_hard_bounce(&self) -> bool {
+        self.action.eq_ignore_ascii_case("failed") && self.status.starts_with('5')
+    }
+}
+
+/// MIME types of the machine-readable part of a bounce.
+pub fn is_delivery_status(content_type: &str) -> bool {
+    let t = content_type.to_ascii_lowercase();
+    t == "message/delivery-status" || t == "message/global-delivery-status"
+}
+
+/// Parses a `message/delivery-status` body into its per-recipient reports.
+/// The leading per-message block is skipped.
+pub fn parse(text: &str) -> Vec<Report> {
+    let text = text.replace("\r\n", "\n");
+    text.split("\n\n")
+        .skip(1)
+        .filter_map(|block| {
+            let fields = unfold(block);
+            let field = |name: &str| {
+                fields
+                    .iter()
+                    .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                    .map(|(_, v)| v.as_str())
+            };
+            // "Final-Recipient: rfc822; user@example.com"
+            let recipient = field("Final-Recipient").or(field("Original-Recipient"))?;
+            let recipient = recipient
+                .split_once(';')
+                .map_or(recipient, |(_, addr)| addr)
+                .trim()
+                .trim_start_matches('<')
+                .trim_end_matches('>')
+                .to_ascii_lowercase();
+            Some(Report {
+                recipient,
+                action: field("Action").unwrap_or_default().trim().to_string(),
+                status: field("Status")
+                    .unwrap_or_default()
+                    .split_whitespace()
+                    .next()
+                    .unwrap_or_default()
+                    .to_string(),
+                diagnostic: field("Diagnostic-Code").map(|d| d.trim().to_string()),
+            })
+        })
+        .collect()
+}
+
+/// Splits a header block into `(name, value)` pairs, joining folded lines.
+fn unfold(block: &str) -> Vec<(String, String)> {
+    let mut fields: Vec<(String, String)> = Vec::new();
+    for line in block.lines() {
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = fields.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            fields.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATUS: &str = "Reporting-MTA: dns; mx.example.com\r\n\
+                          Arrival-Date: Mon, 3 Jun 2024 10:00:00 +0000\r\n\
+                          \r\n\
+                          Final-Recipient: rfc822; <Gone@Example.com>\r\n\
+                          Action: failed\r\n\
+                          Status: 5.1.1 (user unknown)\r\n\
+                          Diagnostic-Code: smtp; 550 5.1.1 No such\r\n  \
+                          mailbox\r\n\
+                          \r\n\
+                          Original-Recipient: rfc822;busy@example.org\r\n\
+                          Action: delayed\r\n\
+                          Status: 4.2.2\r\n";
+
+    #[test]
+    fn parses_per_recipient_blocks() {
+        let reports = parse(STATUS);
+        assert_eq!(reports.len(), 2);
+
+        assert_eq!(reports[0].recipient, "gone@example.com");
+        assert_eq!(reports[0].action, "failed");
+        assert_eq!(reports[0].status, "5.1.1");
+        assert_eq!(reports[0].diagnostic.as_deref(), Some("smtp; 550 5.1.1 No such mailbox"));
+        assert!(reports[0].is_hard_bounce());
+
+        assert_eq!(reports[1].recipient, "busy@example.org");
+        assert_eq!(reports[1].diagnostic, None);
+        assert!(!reports[1].is_hard_bounce());
+    }
+
+    #[test]
+    fn skips_blocks_without_a_recipient() {
+        assert!(parse("Reporting-MTA: dns; mx\n\nAction: failed\nStatus: 5.0.0\n").is_empty());
+    }
+
+    #[test]
+    fn recognizes_status_parts() {
+        assert!(is_delivery_status("message/delivery-status"));
+        assert!(is_delivery_status("Message/Global-Delivery-Status"));
+        assert!(!is_delivery_status("text/plain"));
+    }
+}
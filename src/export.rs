@@ -0,0 +1,219 @@
+use anyhow::{Context, Result, bail};
+use futures_util::stream::{self, StreamExt};
+use jiff::Timestamp;
+use jiff::tz::TimeZone;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncWriteExt, BufWriter};
+
+use crate::jmap::JmapClient;
+use crate::progress::Progress;
+
+/// Emails whose metadata is fetched per round trip.
+const PAGE: usize = 100;
+
+/// Message sources downloaded at once.
+const CONCURRENCY: usize = 4;
+
+/// How an export is laid out on disk.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// One mbox file (mboxrd quoting), readable by most mail clients.
+    #[default]
+    Mbox,
+    /// A directory with one `<id>.eml` file per message.
+    Eml,
+}
+
+enum Sink {
+    Mbox(BufWriter<tokio::fs::File>),
+    /// The directory, and whether existing files may be replaced.
+    Eml(PathBuf, bool),
+}
+
+impl Sink {
+    async fn write(&mut self, email: &Value, source: &[u8]) -> Result<()> {
+        match self {
+            Sink::Mbox(out) => {
+                out.write_all(&mbox_entry(email, source)).await?;
+            }
+            Sink::Eml(dir, overwrite) => {
+                let id = email["id"].as_str().unwrap_or_default();
+                let name: String =
+                    id.chars().filter(|c| c.is_ascii_alphanumeric() || *c == '-').collect();
+                write_new(&dir.join(format!("{name}.eml")), source, *overwrite).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes `data` to a new file at `path`. With `overwrite`, an existing
+/// file is removed first rather than opened, so a symlink in its place is
+/// replaced instead of followed.
+async fn write_new(path: &Path, data: &[u8], overwrite: bool) -> Result<()> {
+    if overwrite {
+        match tokio::fs::remove_file(path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(e).with_context(|| format!("failed to replace {}", path.display()));
+            }
+            _ => {}
+        }
+    }
+    let mut file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .await
+        .with_context(|| {
+            if path.exists() {
+                format!("{} already exists (pass overwrite to replace it)", path.display())
+            } else {
+                format!("failed to create {}", path.display())
+            }
+        })?;
+    file.write_all(data).await?;
+    Ok(())
+}
+
+/// Writes up to `max` emails matching `filter` to `dest`, downloading their
+/// sources a few at a time. Messages that fail to download are listed in
+/// the result rather than aborting the export.
+pub async fn export(
+    client: &JmapClient,
+    filter: Value,
+    dest: &Path,
+    format: ExportFormat,
+    overwrite: bool,
+    max: usize,
+    progress: &Progress,
+) -> Result<Value> {
+    let mut ids = client.query_all_ids(filter).await?;
+    let matched = ids.len();
+    ids.truncate(max);
+    let total = ids.len() as u64;
+
+    let mut sink = match format {
+        ExportFormat::Mbox => {
+            let mut options = tokio::fs::OpenOptions::new();
+            options.write(true);
+            if overwrite {
+                options.create(true).truncate(true);
+            } else {
+                options.create_new(true);
+            }
+            let file = options
+                .open(dest)
+                .await
+                .with_context(|| format!("failed to create {}", dest.display()))?;
+            Sink::Mbox(BufWriter::new(file))
+        }
+        ExportFormat::Eml => Sink::Eml(dest.to_path_buf(), overwrite),
+    };
+
+    let mut exported = 0u64;
+    let mut bytes = 0u64;
+    let mut failed = Vec::new();
+    for chunk in ids.chunks(PAGE) {
+        let emails = client
+            .get_email_properties(chunk, &["id", "blobId", "receivedAt", "from"])
+            .await?;
+        let mut downloads = stream::iter(emails)
+            .map(|email| async move {
+                let blob_id = email["blobId"].as_str().unwrap_or_default();
+                let source = client.download_blob(blob_id, "message.eml", "message/rfc822").await;
+                (email, source)
+            })
+            .buffered(CONCURRENCY);
+
+        while let Some((email, source)) = downloads.next().await {
+            if client.is_cancelled() {
+                let dest = dest.display();
+                bail!("cancelled by the client after exporting {exported} emails to {dest}");
+            }
+            match source {
+                Ok(source) => {
+                    sink.write(&email, &source).await?;
+                    exported += 1;
+                    bytes += source.len() as u64;
+                }
+                Err(e) => failed.push(json!({"id": email["id"], "error": format!("{e:#}")})),
+            }
+            let done = exported + failed.len() as u64;
+            progress.report(done, Some(total), format!("exported {done} of {total}"));
+        }
+    }
+    if let Sink::Mbox(out) = &mut sink {
+        out.flush().await?;
+    }
+
+    Ok(json!({
+        "path": dest,
+        "format": format,
+        "matched": matched,
+        "exported": exported,
+        "bytes": bytes,
+        "truncated": matched > ids.len(),
+        "failed": failed,
+    }))
+}
+
+/// One mboxrd entry: a `From ` separator line, the message with line
+/// endings normalized and `From ` lines quoted, and a blank line.
+fn mbox_entry(email: &Value, source: &[u8]) -> Vec<u8> {
+    let sender = email["from"][0]["email"].as_str().unwrap_or("MAILER-DAEMON");
+    let date = email["receivedAt"]
+        .as_str()
+        .and_then(|d| d.parse::<Timestamp>().ok())
+        .unwrap_or_else(Timestamp::now)
+        .to_zoned(TimeZone::UTC)
+        .strftime("%a %b %e %H:%M:%S %Y");
+
+    let mut out = format!("From {sender} {date}\n").into_bytes();
+    let source = source.strip_suffix(b"\n").unwrap_or(source);
+    for line in source.split(|&b| b == b'\n') {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let unquoted = line.iter().position(|&b| b != b'>').map(|i| &line[i..]);
+        if unquoted.is_some_and(|rest| rest.starts_with(b"From ")) {
+            out.push(b'>');
+        }
+        out.extend_from_slice(line);
+        out.push(b'\n');
+    }
+    out.push(b'\n');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn writes_eml_files_only_over_existing_ones_when_asked() {
+        let dir = std::env::temp_dir().join(format!("export-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let outside = dir.join("outside");
+        std::fs::write(&outside, "keep").unwrap();
+        let email = json!({ "id": "M1" });
+
+        let mut sink = Sink::Eml(dir.clone(), false);
+        sink.write(&email, b"first").await.unwrap();
+        let error = sink.write(&email, b"second").await.unwrap_err();
+        assert!(format!("{error:#}").contains("already exists"));
+        assert_eq!(std::fs::read(dir.join("M1.eml")).unwrap(), b"first");
+
+        #[cfg(unix)]
+        {
+            std::fs::remove_file(dir.join("M1.eml")).unwrap();
+            std::os::unix::fs::symlink(&outside, dir.join("M1.eml")).unwrap();
+            assert!(Sink::Eml(dir.clone(), false).write(&email, b"x").await.is_err());
+        }
+        Sink::Eml(dir.clone(), true).write(&email, b"third").await.unwrap();
+        assert_eq!(std::fs::read(dir.join("M1.eml")).unwrap(), b"third");
+        assert_eq!(std::fs::read(&outside).unwrap(), b"keep");
+    }
+}
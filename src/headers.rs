@@ -0,0 +1,215 @@
+use serde_json::{Map, Value, json};
+
+pub const SPAM_STATUS: &str = "header:X-Spam-Status:asText";
+pub const SPAM_RESULT: &str = "header:X-Spam-Result:asText";
+pub const AUTH_RESULTS: &str = "header:Authentication-Results:asText:all";
+pub const CONTENT_TYPE: &str = "header:Content-Type:asText";
+
+/// Header properties requested alongside every Email/get so they can be
+/// folded into structured fields by [`annotate`].
+pub const PROPERTIES: &[&str] = &[SPAM_STATUS, SPAM_RESULT, AUTH_RESULTS, CONTENT_TYPE];
+
+/// Replaces the raw header properties on each email in an Email/get
+/// response with structured fields.
+pub fn annotate(result: &mut Value) {
+    let Some(list) = result["list"].as_array_mut() else {
+        return;
+    };
+    for email in list {
+        let Some(email) = email.as_object_mut() else {
+            continue;
+        };
+        let status = take_text(email, SPAM_STATUS);
+        let tests = take_text(email, SPAM_RESULT);
+        if let Some(spam) = spam_score(status.as_deref(), tests.as_deref()) {
+            email.insert("spam_score".into(), spam);
+        }
+
+        // Headers are listed top-down, so the first instance is the one added
+        // by the receiving server; later ones come from upstream hops and can
+        // be forged by the sender.
+        let auth = email
+            .remove(AUTH_RESULTS)
+            .and_then(|v| v.as_array()?.first()?.as_str().map(auth_results));
+        if let Some(auth) = auth {
+            email.insert("auth".into(), auth);
+        }
+
+        let content_type = take_text(email, CONTENT_TYPE);
+        if let Some(scheme) = content_type.as_deref().and_then(encryption) {
+            email.insert("encrypted".into(), json!(scheme));
+        }
+    }
+}
+
+fn take_text(email: &mut Map<String, Value>, property: &str) -> Option<String> {
+    email
+        .remove(property)
+        .and_then(|v| v.as_str().map(|s| s.trim().to_string()))
+        .filter(|s| !s.is_empty())
+}
+
+/// Parses `X-Spam-Status` ("Yes, score=7.3 required=5.0 ...") and
+/// `X-Spam-Result` ("SYMBOL (1.5), OTHER (-0.1)") into one object.
+fn spam_score(status: Option<&str>, tests: Option<&str>) -> Option<Value> {
+    if status.is_none() && tests.is_none() {
+        return None;
+    }
+
+    let mut out = json!({});
+
+    if let Some(status) = status {
+        let verdict = status.split(',').next().unwrap_or_default().trim();
+        out["is_spam"] = json!(verdict.eq_ignore_ascii_case("yes"));
+        for field in status.split(|c: char| c == ',' || c.is_whitespace()) {
+            if let Some(score) = field.strip_prefix("score=") {
+                out["score"] = json!(score.parse::<f64>().ok());
+            } else if let Some(required) = field.strip_prefix("required=") {
+                out["required"] = json!(required.parse::<f64>().ok());
+            }
+        }
+    }
+
+    if let Some(tests) = tests {
+        let parsed: Vec<Value> = tests
+            .split(',')
+            .filter_map(|t| {
+                let t = t.trim();
+                if t.is_empty() {
+                    return None;
+                }
+                let (name, score) = match t.split_once('(') {
+                    Some((name, rest)) => {
+                        (name.trim(), rest.trim_end_matches(')').trim().parse::<f64>().ok())
+                    }
+                    None => (t, None),
+                };
+                Some(json!({"name": name, "score": score}))
+            })
+            .collect();
+        out["tests"] = json!(parsed);
+    }
+
+    Some(out)
+}
+
+/// Names the encryption scheme of a top-level `Content-Type`, if any:
+/// "pgp" for PGP/MIME (RFC 3156), "smime" for S/MIME enveloped data.
+fn encryption(content_type: &str) -> Option<&'static str> {
+    let lower = content_type.to_ascii_lowercase();
+    let mime = lower.split(';').next().unwrap_or_default().trim();
+    match mime {
+        "multipart/encrypted" if lower.contains("application/pgp-encrypted") => Some("pgp"),
+        "application/pkcs7-mime" | "application/x-pkcs7-mime"
+            if !lower.contains("signed-data") =>
+        {
+            Some("smime")
+        }
+        _ => None,
+    }
+}
+
+/// Parses an `Authentication-Results` value ("mx.example.com; spf=pass ...;
+/// dkim=pass ...; dmarc=fail ...") into `{spf, dkim, dmarc}`. Methods the
+/// header doesn't mention are reported as `"none"`.
+fn auth_results(header: &str) -> Value {
+    let mut spf = None;
+    let mut dkim = None;
+    let mut dmarc = None;
+
+    // The first segment is the authserv-id, not a result.
+    for entry in header.split(';').skip(1) {
+        let Some((method, rest)) = entry.trim().split_once('=') else {
+            continue;
+        };
+        let result = rest
+            .split(|c: char| c.is_whitespace() || c == '(')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        match method.trim().to_ascii_lowercase().as_str() {
+            "spf" => spf = spf.or(Some(result)),
+            // A message can carry several signatures; one passing is enough.
+            "dkim" if dkim.as_deref() != Some("pass") => dkim = Some(result),
+            "dmarc" => dmarc = dmarc.or(Some(result)),
+            _ => {}
+        }
+    }
+
+    json!({
+        "spf": spf.unwrap_or_else(|| "none".into()),
+        "dkim": dkim.unwrap_or_else(|| "none".into()),
+        "dmarc": dmarc.unwrap_or_else(|| "none".into()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_spam_status_and_tests() {
+        let spam = spam_score(
+            Some("Yes, score=7.3 required=5.0 tests=BAYES_99"),
+            Some("BAYES_99 (3.5), DKIM_INVALID (0.1), MISSING_DATE"),
+        )
+        .unwrap();
+        assert_eq!(spam["is_spam"], true);
+        assert_eq!(spam["score"], 7.3);
+        assert_eq!(spam["required"], 5.0);
+        assert_eq!(
+            spam["tests"],
+            json!([
+                {"name": "BAYES_99", "score": 3.5},
+                {"name": "DKIM_INVALID", "score": 0.1},
+                {"name": "MISSING_DATE", "score": null}
+            ])
+        );
+        assert_eq!(spam_score(Some("No, score=-1.2"), None).unwrap()["is_spam"], false);
+        assert_eq!(spam_score(None, None), None);
+    }
+
+    #[test]
+    fn reads_auth_results() {
+        let auth = auth_results(
+            "mx.example.com; spf=pass smtp.mailfrom=a.com; dkim=fail (bad sig) header.d=a.com; \
+             dkim=pass header.d=b.com; dmarc=FAIL header.from=a.com",
+        );
+        assert_eq!(auth, json!({"spf": "pass", "dkim": "pass", "dmarc": "fail"}));
+
+        // A method named in the authserv-id segment isn't a result.
+        let auth = auth_results("spf=pass.example.com; dkim=none");
+        assert_eq!(auth, json!({"spf": "none", "dkim": "none", "dmarc": "none"}));
+    }
+
+    #[test]
+    fn detects_encryption_schemes() {
+        let pgp = "multipart/encrypted; protocol=\"application/pgp-encrypted\"; boundary=x";
+        assert_eq!(encryption(pgp), Some("pgp"));
+        let smime = "application/pkcs7-mime; smime-type=enveloped-data; name=smime.p7m";
+        assert_eq!(encryption(smime), Some("smime"));
+        assert_eq!(encryption("application/pkcs7-mime; smime-type=signed-data"), None);
+        assert_eq!(encryption("multipart/signed; protocol=\"application/pgp-signature\""), None);
+    }
+
+    #[test]
+    fn annotate_replaces_raw_headers() {
+        let mut result = json!({"list": [{
+            "id": "M1",
+            SPAM_STATUS: " No, score=0.5 ",
+            SPAM_RESULT: null,
+            AUTH_RESULTS: ["mx.local; spf=pass; dkim=pass; dmarc=pass", "upstream; spf=fail"],
+            CONTENT_TYPE: "text/plain"
+        }]});
+        annotate(&mut result);
+        assert_eq!(
+            result["list"][0],
+            json!({
+                "id": "M1",
+                "spam_score": {"is_spam": false, "score": 0.5},
+                "auth": {"spf": "pass", "dkim": "pass", "dmarc": "pass"}
+            })
+        );
+    }
+}
@@ -0,0 +1,354 @@
+use serde_json::Value;
+
+/// Elements whose content is never shown as text.
+const HIDDEN: &[&str] = &["head", "script", "style", "title", "template", "noscript"];
+
+/// Elements that start on a new line.
+const BLOCKS: &[&str] = &[
+    "address", "article", "aside", "blockquote", "center", "dd", "div", "dl", "dt", "figure",
+    "footer", "form", "header", "hr", "main", "nav", "ol", "p", "section", "table", "tbody",
+    "thead", "tfoot", "tr", "ul",
+];
+
+/// Renders an HTML body as plain text: tags stripped, hidden elements
+/// dropped, paragraphs and list items kept on their own lines, headings
+/// marked with `#`, and link targets shown after their text.
+pub fn to_text(html: &str) -> String {
+    let mut out = Writer::default();
+    let mut hidden: Option<String> = None;
+    let mut pre = 0usize;
+    let mut link: Option<(String, usize)> = None;
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        if hidden.is_none() {
+            out.text(&decode_entities(&rest[..start]), pre > 0);
+        }
+        rest = &rest[start..];
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let tag_like = rest[1..]
+            .starts_with(|c: char| c.is_ascii_alphabetic() || matches!(c, '/' | '!' | '?'));
+        if !tag_like {
+            // A stray '<' rather than a tag.
+            if hidden.is_none() {
+                out.text("<", pre > 0);
+            }
+            rest = &rest[1..];
+            continue;
+        }
+        let Some(end) = tag_end(rest) else {
+            // Cut off mid-tag, e.g. by maxBodyValueBytes.
+            rest = "";
+            break;
+        };
+        let tag = Tag::parse(&rest[1..end]);
+        rest = &rest[end + 1..];
+
+        if let Some(name) = &hidden {
+            if tag.closing && tag.name == *name {
+                hidden = None;
+            }
+            continue;
+        }
+        if !tag.closing && !tag.self_closing && HIDDEN.contains(&tag.name.as_str()) {
+            hidden = Some(tag.name);
+            continue;
+        }
+
+        match (tag.name.as_str(), tag.closing) {
+            ("br", _) => out.newline(),
+            ("pre", false) => {
+                out.block();
+                pre += 1;
+            }
+            ("pre", true) => {
+                pre = pre.saturating_sub(1);
+                out.block();
+            }
+            ("li", false) => {
+                out.newline();
+                out.raw("- ");
+            }
+            ("hr", false) => {
+                out.block();
+                out.raw("---");
+                out.block();
+            }
+            ("td" | "th", true) => out.text(" ", false),
+            (name, false) if is_heading(name) => {
+                out.block();
+                let level = name[1..].parse().unwrap_or(1);
+                out.raw(&format!("{} ", "#".repeat(level)));
+            }
+            (name, true) if is_heading(name) => out.block(),
+            ("a", false) => {
+                let href = tag.attribute("href").unwrap_or_default();
+                link = Some((href, out.buf.len()));
+            }
+            ("a", true) => {
+                if let Some((href, from)) = link.take() {
+                    let label = out.buf.get(from..).unwrap_or_default().trim().to_string();
+                    let target = href.strip_prefix("mailto:").unwrap_or(&href);
+                    let shown = !href.is_empty() && !href.starts_with('#') && label != target;
+                    if shown && !href.starts_with("javascript:") {
+                        out.raw(&format!(" ({href})"));
+                    }
+                }
+            }
+            ("img", _) => {
+                let alt = tag.attribute("alt").unwrap_or_default();
+                if !alt.trim().is_empty() {
+                    out.text(&format!("[{}]", alt.trim()), false);
+                }
+            }
+            (name, _) if BLOCKS.contains(&name) => out.block(),
+            _ => {}
+        }
+    }
+    if hidden.is_none() {
+        out.text(&decode_entities(rest), pre > 0);
+    }
+    out.finish()
+}
+
+/// Replaces HTML body values that JMAP put in `textBody` (messages with no
+/// plain-text alternative) with their plain-text rendering, on every email
+/// in an Email/get response.
+pub fn convert_text_bodies(result: &mut Value) {
+    for email in result["list"].as_array_mut().into_iter().flatten() {
+        convert_email(email);
+    }
+}
+
+/// As [`convert_text_bodies`], for a single email.
+pub fn convert_email(email: &mut Value) {
+    let html_parts: Vec<String> = email["textBody"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|part| part["type"].as_str() == Some("text/html"))
+        .filter_map(|part| part["partId"].as_str().map(String::from))
+        .collect();
+    for part in html_parts {
+        let value = &mut email["bodyValues"][part.as_str()]["value"];
+        if let Some(html) = value.as_str() {
+            *value = Value::String(to_text(html));
+        }
+    }
+}
+
+fn is_heading(name: &str) -> bool {
+    matches!(name, "h1" | "h2" | "h3" | "h4" | "h5" | "h6")
+}
+
+/// Index of the `>` closing the tag that starts `s`, skipping quoted
+/// attribute values.
+fn tag_end(s: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in s.char_indices().skip(1) {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '>') => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+struct Tag<'a> {
+    name: String,
+    closing: bool,
+    self_closing: bool,
+    attributes: &'a str,
+}
+
+impl<'a> Tag<'a> {
+    fn parse(inner: &'a str) -> Self {
+        let (closing, inner) = match inner.strip_prefix('/') {
+            Some(rest) => (true, rest),
+            None => (false, inner),
+        };
+        let self_closing = inner.ends_with('/');
+        let name_end = inner
+            .find(|c: char| c.is_whitespace() || c == '/' || c == '>')
+            .unwrap_or(inner.len());
+        Self {
+            name: inner[..name_end].to_ascii_lowercase(),
+            closing,
+            self_closing,
+            attributes: &inner[name_end..],
+        }
+    }
+
+    /// The decoded value of attribute `name`, if present.
+    fn attribute(&self, name: &str) -> Option<String> {
+        let mut rest = self.attributes;
+        loop {
+            rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+            if rest.is_empty() {
+                return None;
+            }
+            let key_end = rest
+                .find(|c: char| c.is_whitespace() || c == '=' || c == '/')
+                .unwrap_or(rest.len());
+            let key = &rest[..key_end];
+            rest = rest[key_end..].trim_start();
+            let value = match rest.strip_prefix('=') {
+                Some(after) => {
+                    let after = after.trim_start();
+                    let (value, remaining) = match after.chars().next() {
+                        Some(q @ ('"' | '\'')) => {
+                            let body = &after[1..];
+                            let end = body.find(q).unwrap_or(body.len());
+                            (&body[..end], body.get(end + 1..).unwrap_or(""))
+                        }
+                        _ => {
+                            let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                            (&after[..end], &after[end..])
+                        }
+                    };
+                    rest = remaining;
+                    value
+                }
+                None => "",
+            };
+            if key.eq_ignore_ascii_case(name) {
+                return Some(decode_entities(value).trim().to_string());
+            }
+        }
+    }
+}
+
+/// Accumulates text, collapsing whitespace outside `<pre>` and keeping at
+/// most one blank line between blocks.
+#[derive(Default)]
+struct Writer {
+    buf: String,
+}
+
+impl Writer {
+    fn text(&mut self, text: &str, preformatted: bool) {
+        if preformatted {
+            self.buf.push_str(text);
+            return;
+        }
+        for c in text.chars() {
+            if c.is_whitespace() {
+                if !self.buf.is_empty() && !self.buf.ends_with([' ', '\n']) {
+                    self.buf.push(' ');
+                }
+            } else {
+                self.buf.push(c);
+            }
+        }
+    }
+
+    fn raw(&mut self, text: &str) {
+        self.buf.push_str(text);
+    }
+
+    fn newline(&mut self) {
+        self.trim_trailing_spaces();
+        self.buf.push('\n');
+    }
+
+    fn block(&mut self) {
+        self.trim_trailing_spaces();
+        if self.buf.is_empty() || self.buf.ends_with("\n\n") {
+            return;
+        }
+        self.buf.push_str(if self.buf.ends_with('\n') { "\n" } else { "\n\n" });
+    }
+
+    fn trim_trailing_spaces(&mut self) {
+        let trimmed = self.buf.trim_end_matches(' ').len();
+        self.buf.truncate(trimmed);
+    }
+
+    fn finish(self) -> String {
+        let mut out = String::with_capacity(self.buf.len());
+        let mut blank = 0;
+        for line in self.buf.lines().map(str::trim_end) {
+            if line.is_empty() {
+                blank += 1;
+                if blank > 1 {
+                    continue;
+                }
+            } else {
+                blank = 0;
+            }
+            out.push_str(line);
+            out.push('\n');
+        }
+        out.trim().to_string()
+    }
+}
+
+/// Decodes the character references common in email HTML.
+fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest[1..]
+            .find(';')
+            .filter(|&end| end <= 10)
+            .and_then(|end| Some((entity(&rest[1..end + 1])?, end + 2)));
+        match decoded {
+            Some((c, len)) => {
+                out.push_str(&c);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn entity(name: &str) -> Option<String> {
+    if let Some(number) = name.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return char::from_u32(code).map(|c| c.to_string());
+    }
+    let c = match name {
+        "amp" => "&",
+        "lt" => "<",
+        "gt" => ">",
+        "quot" => "\"",
+        "apos" => "'",
+        "nbsp" => " ",
+        "copy" => "©",
+        "reg" => "®",
+        "trade" => "™",
+        "hellip" => "…",
+        "mdash" => "—",
+        "ndash" => "–",
+        "lsquo" => "‘",
+        "rsquo" => "’",
+        "ldquo" => "“",
+        "rdquo" => "”",
+        "bull" => "•",
+        "middot" => "·",
+        "euro" => "€",
+        "pound" => "£",
+        "zwnj" | "zwj" | "shy" => "",
+        _ => return None,
+    };
+    Some(c.to_string())
+}
@@ -0,0 +1,244 @@
+use anyhow::{Context, Result};
+use rusqlite::{Connection, OptionalExtension, params};
+use serde_json::{Value, json};
+use std::sync::Mutex;
+
+use crate::jmap::JmapClient;
+
+/// Properties mirrored into the local index.
+const PROPERTIES: &[&str] = &["id", "threadId", "from", "subject", "receivedAt", "preview"];
+
+/// An on-disk SQLite FTS5 index of email metadata (sender, subject and
+/// preview), kept current with Email/changes so lookups don't need a round
+/// trip to the server. Bodies are not indexed.
+pub struct LocalIndex {
+    conn: Mutex<Connection>,
+}
+
+impl LocalIndex {
+    /// Opens the index at `STALWART_INDEX_PATH`, or returns `None` when the
+    /// local index is not enabled.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(path) = std::env::var("STALWART_INDEX_PATH") else {
+            return Ok(None);
+        };
+        let conn = Connection::open(&path)
+            .with_context(|| format!("failed to open local index {path}"))?;
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE IF NOT EXISTS sync_state (
+                 account_id TEXT PRIMARY KEY,
+                 email_state TEXT NOT NULL
+             );
+             CREATE TABLE IF NOT EXISTS emails (
+                 account_id TEXT NOT NULL,
+                 id TEXT NOT NULL,
+                 thread_id TEXT,
+                 received_at TEXT,
+                 sender TEXT,
+                 subject TEXT,
+                 preview TEXT,
+                 PRIMARY KEY (account_id, id)
+             );
+             CREATE VIRTUAL TABLE IF NOT EXISTS emails_fts USING fts5(
+                 account_id UNINDEXED, id UNINDEXED, sender, subject, preview,
+                 tokenize = 'trigram'
+             );",
+        )
+        .context("failed to initialise local index")?;
+        Ok(Some(Self {
+            conn: Mutex::new(conn),
+        }))
+    }
+
+    /// Brings the index for `client`'s account up to date: a full load the
+    /// first time (or when the server can't calculate changes), otherwise
+    /// only what Email/changes reports since the last sync.
+    pub async fn sync(&self, client: &JmapClient) -> Result<()> {
+        let account = client.account_id().to_string();
+        let since = self.stored_state(&account)?;
+
+        let Some(mut state) = since else {
+            return self.full_sync(client).await;
+        };
+
+        loop {
+            let changes = match client.email_changes(&state).await {
+                Ok(changes) => changes,
+                Err(e) if e.to_string().contains("cannotCalculateChanges") => {
+                    return self.full_sync(client).await;
+                }
+                Err(e) => return Err(e),
+            };
+
+            let mut changed: Vec<String> = Vec::new();
+            for key in ["created", "updated"] {
+                changed.extend(
+                    changes[key]
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .filter_map(|id| id.as_str().map(String::from)),
+                );
+            }
+            let destroyed: Vec<String> = changes["destroyed"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|id| id.as_str().map(String::from))
+                .collect();
+
+            let emails = client.get_email_properties(&changed, PROPERTIES).await?;
+            state = changes["newState"]
+                .as_str()
+                .context("Email/changes returned no newState")?
+                .to_string();
+            self.apply(&account, &emails, &destroyed, &state)?;
+
+            if changes["hasMoreChanges"].as_bool() != Some(true) {
+                return Ok(());
+            }
+        }
+    }
+
+    async fn full_sync(&self, client: &JmapClient) -> Result<()> {
+        let account = client.account_id().to_string();
+        tracing::debug!(%account, "rebuilding local index");
+
+        // Capture the state first so changes made during the load are
+        // replayed on the next incremental sync.
+        let state = client.email_state().await?;
+        let ids = client.query_all_ids(json!({})).await?;
+        let emails = client.get_email_properties(&ids, PROPERTIES).await?;
+
+        {
+            let conn = self.conn.lock().unwrap();
+            conn.execute("DELETE FROM emails WHERE account_id = ?1", params![account])?;
+            conn.execute("DELETE FROM emails_fts WHERE account_id = ?1", params![account])?;
+        }
+        self.apply(&account, &emails, &[], &state)
+    }
+
+    fn stored_state(&self, account: &str) -> Result<Option<String>> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn
+            .query_row(
+                "SELECT email_state FROM sync_state WHERE account_id = ?1",
+                params![account],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    fn apply(&self, account: &str, upserts: &[Value], destroyed: &[String], state: &str) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        for id in destroyed {
+            tx.execute("DELETE FROM emails WHERE account_id = ?1 AND id = ?2", params![account, id])?;
+            tx.execute("DELETE FROM emails_fts WHERE account_id = ?1 AND id = ?2", params![account, id])?;
+        }
+
+        for email in upserts {
+            let Some(id) = email["id"].as_str() else {
+                continue;
+            };
+            let sender = sender_text(&email["from"]);
+            let subject = email["subject"].as_str().unwrap_or_default();
+            let preview = email["preview"].as_str().unwrap_or_default();
+
+            tx.execute(
+                "INSERT OR REPLACE INTO emails
+                     (account_id, id, thread_id, received_at, sender, subject, preview)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    account,
+                    id,
+                    email["threadId"].as_str(),
+                    email["receivedAt"].as_str(),
+                    sender,
+                    subject,
+                    preview
+                ],
+            )?;
+            tx.execute("DELETE FROM emails_fts WHERE account_id = ?1 AND id = ?2", params![account, id])?;
+            tx.execute(
+                "INSERT INTO emails_fts (account_id, id, sender, subject, preview)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![account, id, sender, subject, preview],
+            )?;
+        }
+
+        tx.execute(
+            "INSERT OR REPLACE INTO sync_state (account_id, email_state) VALUES (?1, ?2)",
+            params![account, state],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Matches `query` against sender, subject and preview. Terms are
+    /// matched as substrings, so partial names and addresses work; terms
+    /// shorter than three characters fall back to a plain LIKE scan.
+    pub fn search(&self, account: &str, query: &str, limit: u32) -> Result<Vec<Value>> {
+        let terms: Vec<&str> = query.split_whitespace().collect();
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let conn = self.conn.lock().unwrap();
+        let to_json = |row: &rusqlite::Row| -> rusqlite::Result<Value> {
+            Ok(json!({
+                "id": row.get::<_, String>(0)?,
+                "threadId": row.get::<_, Option<String>>(1)?,
+                "receivedAt": row.get::<_, Option<String>>(2)?,
+                "from": row.get::<_, Option<String>>(3)?,
+                "subject": row.get::<_, Option<String>>(4)?,
+                "preview": row.get::<_, Option<String>>(5)?,
+            }))
+        };
+
+        if terms.iter().all(|t| t.chars().count() >= 3) {
+            let fts_query = terms
+                .iter()
+                .map(|t| format!("\"{}\"", t.replace('"', "\"\"")))
+                .collect::<Vec<_>>()
+                .join(" AND ");
+            let mut stmt = conn.prepare(
+                "SELECT e.id, e.thread_id, e.received_at, e.sender, e.subject, e.preview
+                 FROM emails_fts f
+                 JOIN emails e ON e.account_id = f.account_id AND e.id = f.id
+                 WHERE emails_fts MATCH ?1 AND f.account_id = ?2
+                 ORDER BY e.received_at DESC
+                 LIMIT ?3",
+            )?;
+            let rows = stmt.query_map(params![fts_query, account, limit], to_json)?;
+            return Ok(rows.collect::<rusqlite::Result<_>>()?);
+        }
+
+        let pattern = format!("%{}%", query.trim());
+        let mut stmt = conn.prepare(
+            "SELECT id, thread_id, received_at, sender, subject, preview
+             FROM emails
+             WHERE account_id = ?1 AND (sender LIKE ?2 OR subject LIKE ?2 OR preview LIKE ?2)
+             ORDER BY received_at DESC
+             LIMIT ?3",
+        )?;
+        let rows = stmt.query_map(params![account, pattern, limit], to_json)?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+}
+
+/// Flattens a JMAP address list into "Name <email>, ..." for indexing.
+pub fn sender_text(from: &Value) -> String {
+    from.as_array()
+        .into_iter()
+        .flatten()
+        .map(|a| match (a["name"].as_str(), a["email"].as_str()) {
+            (Some(name), Some(email)) if !name.is_empty() => format!("{name} <{email}>"),
+            (_, Some(email)) => email.to_string(),
+            _ => String::new(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
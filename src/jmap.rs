@@ -1,8 +1,39 @@
 use anyhow::{Context, Result, bail};
 use reqwest::Client;
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
 use serde_json::{Value, json};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tokio_util::sync::CancellationToken;
+
+use crate::headers;
+use crate::html;
+use crate::mime;
+use crate::progress::Progress;
+use crate::sieve::{self, SenderLists};
+use crate::tnef;
+use crate::wirelog::WireLog;
+
+/// How long the first queued call waits for concurrent calls to join its batch.
+const BATCH_WINDOW: Duration = Duration::from_millis(10);
+
+/// Error for work abandoned because the MCP request was cancelled.
+const CANCELLED: &str = "cancelled by the client";
+
+/// Blobs larger than this are uploaded in parts of this size when the
+/// server supports joining them.
+const UPLOAD_PART_SIZE: usize = 8 * 1024 * 1024;
+
+/// Default cap on each body value returned by Email/get; longer bodies
+/// come back with `isTruncated` set.
+pub const MAX_BODY_VALUE_BYTES: u32 = 65536;
+
+const BLOB_CAPABILITY: &str = "urn:ietf:params:jmap:blob";
+const SIEVE_CAPABILITY: &str = "urn:ietf:params:jmap:sieve";
+const VACATION_CAPABILITY: &str = "urn:ietf:params:jmap:vacationresponse";
 
 #[derive(Clone)]
 pub struct JmapClient {
@@ -11,40 +42,170 @@ pub struct JmapClient {
     username: String,
     password: String,
     account_id: String,
+    accounts: Arc<HashMap<String, String>>,
+    upload_url: String,
+    download_url: String,
+    /// EventSource push endpoint template, if the server offers one.
+    event_source_url: Option<String>,
+    using: Vec<String>,
+    max_calls_in_request: usize,
+    max_objects_in_set: usize,
+    max_objects_in_get: usize,
+    /// Largest single upload the server accepts (core `maxSizeUpload`).
+    max_size_upload: Option<u64>,
+    /// Per-account `maxSizeAttachmentsPerEmail`, for accounts that set one.
+    attachment_limits: Arc<HashMap<String, u64>>,
+    /// Per-account `maxDelayedSend` in seconds, for accounts whose server
+    /// offers FUTURERELEASE.
+    delayed_send_limits: Arc<HashMap<String, u64>>,
+    /// Accounts whose server takes DSN (RFC 3461) envelope parameters.
+    dsn_accounts: Arc<HashSet<String>>,
+    /// Mailbox IDs by `(account, role)`, filled on first lookup.
+    role_ids: Arc<Mutex<HashMap<(String, String), String>>>,
+    queue: Arc<Mutex<Vec<PendingCall>>>,
+    wire_log: Option<Arc<WireLog>>,
+    /// Set on clients handed to a single tool call, so its JMAP work stops
+    /// when the MCP client cancels the request.
+    cancel: Option<CancellationToken>,
+}
+
+/// An outgoing plain-text message.
+#[derive(Debug, Clone, Default)]
+pub struct Draft {
+    pub from: String,
+    /// Display name for `from`, if any.
+    pub from_name: Option<String>,
+    /// Identity to send as; defaults to the one matching the login.
+    pub identity_id: Option<String>,
+    pub to: Vec<String>,
+    pub cc: Vec<String>,
+    pub bcc: Vec<String>,
+    pub subject: String,
+    pub body: String,
+    /// HTML alternative to `body`; with it the message is sent as
+    /// multipart/alternative.
+    pub html_body: Option<String>,
+    /// Message-IDs this replies to, for threading.
+    pub in_reply_to: Vec<String>,
+    pub references: Vec<String>,
+    /// Hold delivery until this time (RFC 4865 FUTURERELEASE); only used
+    /// when sending.
+    pub send_at: Option<jiff::Timestamp>,
+    /// DSN NOTIFY conditions per recipient, e.g. `["SUCCESS", "FAILURE"]`.
+    pub notify: Vec<String>,
+    /// DSN RET: how much of the message a report returns, FULL or HDRS.
+    pub return_content: Option<String>,
+    /// Already-uploaded files to attach, as JMAP EmailBodyPart objects
+    /// referencing a `blobId`.
+    pub attachments: Vec<Value>,
+}
+
+impl Draft {
+    /// The Email/set creation object for this draft, filed in `mailbox_id`.
+    fn to_email(&self, mailbox_id: &str) -> Value {
+        let addresses = |list: &[String]| -> Vec<Value> {
+            list.iter().map(|a| json!({"email": a})).collect()
+        };
+
+        let mut email = json!({
+            "from": [{"email": self.from, "name": self.from_name}],
+            "to": addresses(&self.to),
+            "subject": self.subject,
+            "keywords": {"$draft": true, "$seen": true},
+            "bodyValues": {
+                "body": {
+                    "value": self.body,
+                    "charset": "utf-8"
+                }
+            },
+            "textBody": [{"partId": "body", "type": "text/plain"}],
+            "mailboxIds": {mailbox_id: true}
+        });
+
+        if !self.cc.is_empty() {
+            email["cc"] = json!(addresses(&self.cc));
+        }
+        if !self.bcc.is_empty() {
+            email["bcc"] = json!(addresses(&self.bcc));
+        }
+        if let Some(html) = &self.html_body {
+            email["bodyValues"]["html"] = json!({ "value": html, "charset": "utf-8" });
+            email["htmlBody"] = json!([{"partId": "html", "type": "text/html"}]);
+        }
+        if !self.in_reply_to.is_empty() {
+            email["inReplyTo"] = json!(self.in_reply_to);
+        }
+        if !self.references.is_empty() {
+            email["references"] = json!(self.references);
+        }
+        if !self.attachments.is_empty() {
+            email["attachments"] = json!(self.attachments);
+        }
+        email
+    }
+}
+
+/// A single method call waiting to be sent as part of a batched request.
+struct PendingCall {
+    method: String,
+    args: Value,
+    reply: oneshot::Sender<Result<Value, String>>,
 }
 
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct Session {
     api_url: String,
+    upload_url: String,
+    download_url: String,
+    #[serde(default)]
+    event_source_url: Option<String>,
     accounts: HashMap<String, AccountInfo>,
     primary_accounts: HashMap<String, String>,
+    #[serde(default)]
+    capabilities: HashMap<String, Value>,
 }
 
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct AccountInfo {
-    #[allow(dead_code)]
     name: String,
+    #[serde(default)]
+    account_capabilities: HashMap<String, Value>,
 }
 
 impl JmapClient {
-    pub async fn connect(session_url: &str, username: &str, password: &str) -> Result<Self> {
+    pub async fn connect(
+        session_url: &str,
+        username: &str,
+        password: &str,
+        wire_log: Option<Arc<WireLog>>,
+    ) -> Result<Self> {
         let http = Client::builder()
             .user_agent("mcp-server-stalwart/0.1.0")
             .build()?;
 
-        let session: Session = http
+        tracing::debug!(%session_url, "fetching JMAP session");
+        if let Some(log) = &wire_log {
+            log.request("GET", session_url, "");
+        }
+        let resp = http
             .get(session_url)
             .basic_auth(username, Some(password))
             .send()
             .await
-            .context("failed to fetch JMAP session")?
-            .error_for_status()
-            .context("JMAP session auth failed")?
-            .json()
-            .await
-            .context("failed to parse JMAP session")?;
+            .context("failed to fetch JMAP session")?;
+        let status = resp.status();
+        let body = resp.text().await.context("failed to read JMAP session")?;
+        if let Some(log) = &wire_log {
+            log.response(status.as_u16(), &body);
+        }
+        tracing::debug!(%status, bytes = body.len(), "JMAP session response");
+        if !status.is_success() {
+            bail!("JMAP session auth failed: HTTP {status}");
+        }
+        let session: Session =
+            serde_json::from_str(&body).context("failed to parse JMAP session")?;
 
         let account_id = session
             .primary_accounts
@@ -56,55 +217,491 @@ impl JmapClient {
             bail!("account {account_id} not in session");
         }
 
+        let core = session.capabilities.get("urn:ietf:params:jmap:core");
+        let max_calls_in_request = core
+            .and_then(|core| core["maxCallsInRequest"].as_u64())
+            .map(|n| n.max(1) as usize)
+            .unwrap_or(16);
+        let max_objects_in_set = core
+            .and_then(|core| core["maxObjectsInSet"].as_u64())
+            .map(|n| n.max(1) as usize)
+            .unwrap_or(500);
+        let max_objects_in_get = core
+            .and_then(|core| core["maxObjectsInGet"].as_u64())
+            .map(|n| n.max(1) as usize)
+            .unwrap_or(500);
+        let max_size_upload = core.and_then(|core| core["maxSizeUpload"].as_u64());
+
+        let attachment_limits = session
+            .accounts
+            .iter()
+            .filter_map(|(id, info)| {
+                let mail = info.account_capabilities.get("urn:ietf:params:jmap:mail")?;
+                Some((id.clone(), mail["maxSizeAttachmentsPerEmail"].as_u64()?))
+            })
+            .collect();
+
+        let delayed_send_limits = session
+            .accounts
+            .iter()
+            .filter_map(|(id, info)| {
+                let submission = info.account_capabilities.get("urn:ietf:params:jmap:submission")?;
+                let extensions = submission["submissionExtensions"].as_object()?;
+                if !extensions.keys().any(|k| k.eq_ignore_ascii_case("FUTURERELEASE")) {
+                    return None;
+                }
+                let max = submission["maxDelayedSend"].as_u64().filter(|&max| max > 0)?;
+                Some((id.clone(), max))
+            })
+            .collect();
+
+        let dsn_accounts = session
+            .accounts
+            .iter()
+            .filter(|(_, info)| {
+                info.account_capabilities
+                    .get("urn:ietf:params:jmap:submission")
+                    .and_then(|submission| submission["submissionExtensions"].as_object())
+                    .is_some_and(|ext| ext.keys().any(|k| k.eq_ignore_ascii_case("DSN")))
+            })
+            .map(|(id, _)| id.clone())
+            .collect();
+
+        let accounts = session
+            .accounts
+            .iter()
+            .filter(|(_, info)| info.account_capabilities.contains_key("urn:ietf:params:jmap:mail"))
+            .map(|(id, info)| (id.clone(), info.name.clone()))
+            .collect();
+
+        // Only declare optional capabilities the server actually advertises;
+        // unknown entries in `using` fail the whole request.
+        let using = [
+            "urn:ietf:params:jmap:core",
+            "urn:ietf:params:jmap:mail",
+            "urn:ietf:params:jmap:submission",
+            SIEVE_CAPABILITY,
+            VACATION_CAPABILITY,
+            BLOB_CAPABILITY,
+        ]
+        .into_iter()
+        .filter(|c| c.ends_with(":core") || session.capabilities.contains_key(*c))
+        .map(String::from)
+        .collect();
+
+        tracing::debug!(%account_id, api_url = %session.api_url, "JMAP session established");
+
         Ok(Self {
             http,
             api_url: session.api_url,
+            upload_url: session.upload_url,
+            download_url: session.download_url,
+            event_source_url: session.event_source_url,
+            using,
             username: username.to_string(),
             password: password.to_string(),
             account_id,
+            accounts: Arc::new(accounts),
+            max_calls_in_request,
+            max_objects_in_set,
+            max_objects_in_get,
+            max_size_upload,
+            attachment_limits: Arc::new(attachment_limits),
+            delayed_send_limits: Arc::new(delayed_send_limits),
+            dsn_accounts: Arc::new(dsn_accounts),
+            role_ids: Arc::new(Mutex::new(HashMap::new())),
+            queue: Arc::new(Mutex::new(Vec::new())),
+            wire_log,
+            cancel: None,
         })
     }
 
+    /// Issues a single method call. Calls made concurrently within
+    /// `BATCH_WINDOW` are coalesced into one JMAP request.
     async fn call(&self, method: &str, args: Value) -> Result<Value> {
-        let results = self.call_multi(vec![(method, args, "r0")]).await?;
-        Ok(results.into_iter().next().context("empty JMAP response")?)
+        if self.is_cancelled() {
+            bail!(CANCELLED);
+        }
+        let (reply, rx) = oneshot::channel();
+        let is_first = {
+            let mut queue = self.queue.lock().unwrap();
+            queue.push(PendingCall {
+                method: method.to_string(),
+                args,
+                reply,
+            });
+            queue.len() == 1
+        };
+
+        // The first caller schedules the flush on a detached task so the batch
+        // is still sent if that caller's own future gets dropped.
+        if is_first {
+            let client = self.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(BATCH_WINDOW).await;
+                let pending = std::mem::take(&mut *client.queue.lock().unwrap());
+                client.flush(pending).await;
+            });
+        }
+
+        // A cancelled caller stops waiting; the batch still completes for
+        // any other calls that share it.
+        self.cancellable(async {
+            rx.await
+                .context("batched JMAP call was dropped")?
+                .map_err(anyhow::Error::msg)
+        })
+        .await
+    }
+
+    /// A client whose calls fail with "cancelled by the client" once `ct`
+    /// is cancelled.
+    pub fn with_cancellation(&self, ct: CancellationToken) -> Self {
+        Self {
+            cancel: Some(ct),
+            ..self.clone()
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(CancellationToken::is_cancelled)
+    }
+
+    /// Runs `work`, abandoning it if the request is cancelled first.
+    async fn cancellable<T>(&self, work: impl Future<Output = Result<T>>) -> Result<T> {
+        match &self.cancel {
+            Some(ct) => tokio::select! {
+                result = work => result,
+                _ = ct.cancelled() => bail!(CANCELLED),
+            },
+            None => work.await,
+        }
+    }
+
+    async fn flush(&self, mut pending: Vec<PendingCall>) {
+        while !pending.is_empty() {
+            let rest = pending.split_off(pending.len().min(self.max_calls_in_request));
+            let chunk = std::mem::replace(&mut pending, rest);
+
+            let method_calls: Vec<Value> = chunk
+                .iter()
+                .enumerate()
+                .map(|(i, c)| json!([c.method, c.args, format!("c{i}")]))
+                .collect();
+
+            match self.post(method_calls).await {
+                Ok(resp) => {
+                    let mut responses = resp.by_call_id();
+                    for (i, call) in chunk.into_iter().enumerate() {
+                        let result = match responses.remove(&format!("c{i}")) {
+                            Some(r) if r[0].as_str() == Some("error") => {
+                                Err(format!("JMAP error: {}", r[1]))
+                            }
+                            Some(r) => Ok(r[1].clone()),
+                            None => Err("empty JMAP response".to_string()),
+                        };
+                        let _ = call.reply.send(result);
+                    }
+                }
+                Err(e) => {
+                    let msg = format!("{e:#}");
+                    for call in chunk {
+                        let _ = call.reply.send(Err(msg.clone()));
+                    }
+                }
+            }
+        }
     }
 
+    /// Sends `calls` as one request and returns each call's own response,
+    /// in the order given; implicit responses a call adds are left out.
     async fn call_multi(&self, calls: Vec<(&str, Value, &str)>) -> Result<Vec<Value>> {
+        let ids: Vec<&str> = calls.iter().map(|(_, _, id)| *id).collect();
         let method_calls: Vec<Value> = calls
             .into_iter()
             .map(|(method, args, id)| json!([method, args, id]))
             .collect();
 
+        let resp = self.cancellable(self.post(method_calls)).await?;
+
+        let mut responses = resp.by_call_id();
+        let mut results = Vec::with_capacity(ids.len());
+        for id in ids {
+            let call = responses.remove(id).with_context(|| format!("no response to call {id}"))?;
+            if call[0].as_str() == Some("error") {
+                bail!("JMAP error: {}", call[1]);
+            }
+            results.push(call[1].clone());
+        }
+
+        Ok(results)
+    }
+
+    async fn post(&self, method_calls: Vec<Value>) -> Result<JmapResponse> {
         let request = json!({
-            "using": [
-                "urn:ietf:params:jmap:core",
-                "urn:ietf:params:jmap:mail",
-                "urn:ietf:params:jmap:submission"
-            ],
+            "using": self.using,
             "methodCalls": method_calls
         });
 
-        let resp: JmapResponse = self
+        let body = serde_json::to_string(&request)?;
+        tracing::debug!(api_url = %self.api_url, request = %body, "JMAP request");
+        if let Some(log) = &self.wire_log {
+            log.request("POST", &self.api_url, &body);
+        }
+
+        let resp = self
             .http
             .post(&self.api_url)
             .basic_auth(&self.username, Some(&self.password))
-            .json(&request)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body)
             .send()
-            .await?
-            .error_for_status()?
-            .json()
             .await?;
+        let status = resp.status();
+        let text = resp.text().await?;
+        tracing::debug!(%status, response = %text, "JMAP response");
+        if let Some(log) = &self.wire_log {
+            log.response(status.as_u16(), &text);
+        }
+        if !status.is_success() {
+            tracing::error!(%status, api_url = %self.api_url, "JMAP request failed");
+            bail!("JMAP request failed: HTTP {status}");
+        }
 
-        let mut results = Vec::new();
-        for call in resp.method_responses {
-            if call[0].as_str() == Some("error") {
-                bail!("JMAP error: {}", call[1]);
+        let response: JmapResponse =
+            serde_json::from_str(&text).context("failed to parse JMAP response")?;
+        for error in response.method_responses.iter().filter(|r| r[0] == "error") {
+            tracing::warn!(error = %error[1], "JMAP method error");
+        }
+        Ok(response)
+    }
+
+    /// Returns a client bound to another account from the session (e.g. a
+    /// shared mailbox), matched by account ID or name.
+    pub fn with_account(&self, account: &str) -> Result<Self> {
+        let id = if self.accounts.contains_key(account) {
+            account.to_string()
+        } else {
+            self.accounts
+                .iter()
+                .find(|(_, name)| name.eq_ignore_ascii_case(account))
+                .map(|(id, _)| id.clone())
+                .with_context(|| {
+                    let mut known: Vec<&str> = self.accounts.values().map(String::as_str).collect();
+                    known.sort();
+                    format!("unknown account {account:?}; available: {}", known.join(", "))
+                })?
+        };
+        Ok(Self {
+            account_id: id,
+            ..self.clone()
+        })
+    }
+
+    /// Establishes a fresh session with new credentials, keeping the wire
+    /// log and, if it still exists, the currently selected account.
+    pub async fn reconnect(&self, session_url: &str, username: &str, password: &str) -> Result<Self> {
+        let fresh = Self::connect(session_url, username, password, self.wire_log.clone()).await?;
+        match fresh.with_account(&self.account_id) {
+            Ok(client) => Ok(client),
+            Err(_) => Ok(fresh),
+        }
+    }
+
+    /// All mail accounts in the session (personal and delegated), as
+    /// `(id, name)` pairs sorted by name.
+    pub fn accounts(&self) -> Vec<(String, String)> {
+        let mut accounts: Vec<(String, String)> =
+            self.accounts.iter().map(|(id, name)| (id.clone(), name.clone())).collect();
+        accounts.sort_by(|a, b| a.1.cmp(&b.1));
+        accounts
+    }
+
+    pub fn wire_log(&self) -> Option<&WireLog> {
+        self.wire_log.as_deref()
+    }
+
+    /// Uploads raw bytes to the session's upload endpoint and returns the
+    /// server's `{accountId, blobId, type, size}` descriptor.
+    pub async fn upload_blob(&self, data: Vec<u8>, content_type: &str) -> Result<Value> {
+        self.upload_blob_with_progress(data, content_type, &Progress::none()).await
+    }
+
+    /// [`Self::upload_blob`], streaming the body and reporting bytes sent to
+    /// `progress` roughly every percent. Transient failures are retried;
+    /// large blobs go up in parts when the server supports joining them, so
+    /// a retry only repeats the part that failed.
+    pub async fn upload_blob_with_progress(
+        &self,
+        data: Vec<u8>,
+        content_type: &str,
+        progress: &Progress,
+    ) -> Result<Value> {
+        if let Some(max) = self.max_size_upload
+            && data.len() as u64 > max
+        {
+            bail!(
+                "{} is over the server's {} upload limit; share it through a file-storage \
+                 link instead",
+                format_size(data.len() as u64),
+                format_size(max)
+            );
+        }
+
+        let data: Arc<[u8]> = data.into();
+        if data.len() > UPLOAD_PART_SIZE && self.using.iter().any(|c| c == BLOB_CAPABILITY) {
+            return self.upload_in_parts(data, content_type, progress).await;
+        }
+        let range = 0..data.len();
+        self.post_blob_retrying(data, range, content_type, progress).await
+    }
+
+    /// Uploads `data` as separate part blobs, then joins them server-side
+    /// with Blob/upload (RFC 9404).
+    async fn upload_in_parts(&self, data: Arc<[u8]>, content_type: &str, progress: &Progress) -> Result<Value> {
+        let mut parts = Vec::new();
+        for start in (0..data.len()).step_by(UPLOAD_PART_SIZE) {
+            let range = start..(start + UPLOAD_PART_SIZE).min(data.len());
+            let part = self
+                .post_blob_retrying(data.clone(), range, "application/octet-stream", progress)
+                .await?;
+            parts.push(json!({ "blobId": part["blobId"] }));
+        }
+        tracing::debug!(parts = parts.len(), "joining uploaded parts");
+
+        let result = self
+            .call(
+                "Blob/upload",
+                json!({
+                    "accountId": self.account_id,
+                    "create": { "blob": { "data": parts, "type": content_type } }
+                }),
+            )
+            .await?;
+        if let Some(err) = result["notCreated"].get("blob") {
+            bail!("failed to join uploaded parts: {err}");
+        }
+        let created = &result["created"]["blob"];
+        Ok(json!({
+            "accountId": self.account_id,
+            "blobId": created["id"],
+            "type": created["type"].as_str().unwrap_or(content_type),
+            "size": created["size"]
+        }))
+    }
+
+    async fn post_blob_retrying(
+        &self,
+        data: Arc<[u8]>,
+        range: Range<usize>,
+        content_type: &str,
+        progress: &Progress,
+    ) -> Result<Value> {
+        const ATTEMPTS: u32 = 3;
+        let mut attempt = 1;
+        loop {
+            let upload = self.post_blob(data.clone(), range.clone(), content_type, progress);
+            match self.cancellable(upload).await {
+                Err(e) if attempt < ATTEMPTS && is_transient(&e) => {
+                    tracing::warn!(attempt, "blob upload failed, retrying: {e:#}");
+                    tokio::time::sleep(Duration::from_secs(1 << attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
             }
-            results.push(call[1].clone());
         }
+    }
 
-        Ok(results)
+    /// POSTs `data[range]` to the upload endpoint as one blob.
+    async fn post_blob(
+        &self,
+        data: Arc<[u8]>,
+        range: Range<usize>,
+        content_type: &str,
+        progress: &Progress,
+    ) -> Result<Value> {
+        const CHUNK: usize = 64 * 1024;
+
+        let url = self.upload_url.replace("{accountId}", &self.account_id);
+        tracing::debug!(%url, bytes = range.len(), "uploading blob");
+
+        // Progress counts bytes of the whole blob, so parts continue where
+        // the previous one left off.
+        let total = data.len() as u64;
+        let step = (total / 100).max(CHUNK as u64);
+        let progress = progress.clone();
+        let length = range.len() as u64;
+        let mut sent = range.start as u64;
+        let mut reported = sent;
+        let chunks = range.clone().step_by(CHUNK).map(move |start| {
+            let chunk = data[start..(start + CHUNK).min(range.end)].to_vec();
+            sent += chunk.len() as u64;
+            if sent - reported >= step || sent == total {
+                reported = sent;
+                progress.report(sent, Some(total), format!("uploaded {}", format_size(sent)));
+            }
+            Ok::<_, std::io::Error>(chunk)
+        });
+
+        self.http
+            .post(&url)
+            .basic_auth(&self.username, Some(&self.password))
+            .header(reqwest::header::CONTENT_TYPE, content_type)
+            .header(reqwest::header::CONTENT_LENGTH, length)
+            .body(reqwest::Body::wrap_stream(futures_util::stream::iter(chunks)))
+            .send()
+            .await
+            .context("blob upload failed")?
+            .error_for_status()
+            .context("blob upload rejected")?
+            .json()
+            .await
+            .context("failed to parse upload response")
+    }
+
+    /// Downloads a blob via the session's download URL template. A file
+    /// from a `winmail.dat` (see [`Self::expand_tnef`]) is decoded from its
+    /// container.
+    pub async fn download_blob(&self, blob_id: &str, name: &str, content_type: &str) -> Result<Vec<u8>> {
+        let Some((container, index)) = tnef::split_blob_id(blob_id) else {
+            return self.download_server_blob(blob_id, name, content_type).await;
+        };
+        let data = self.download_server_blob(container, "winmail.dat", tnef::CONTENT_TYPE).await?;
+        tnef::decode(&data)?
+            .into_iter()
+            .nth(index)
+            .map(|file| file.data)
+            .with_context(|| format!("winmail.dat {container} has no file {index}"))
+    }
+
+    async fn download_server_blob(
+        &self,
+        blob_id: &str,
+        name: &str,
+        content_type: &str,
+    ) -> Result<Vec<u8>> {
+        let url = self
+            .download_url
+            .replace("{accountId}", &self.account_id)
+            .replace("{blobId}", blob_id)
+            .replace("{name}", &url_escape(name))
+            .replace("{type}", &url_escape(content_type));
+        tracing::debug!(%url, "downloading blob");
+
+        let download = async {
+            self.http
+                .get(&url)
+                .basic_auth(&self.username, Some(&self.password))
+                .send()
+                .await
+                .context("blob download failed")?
+                .error_for_status()
+                .context("blob download rejected")?
+                .bytes()
+                .await
+                .context("failed to read blob")
+        };
+        Ok(self.cancellable(download).await?.to_vec())
     }
 
     pub async fn get_mailboxes(&self) -> Result<Value> {
@@ -124,6 +721,7 @@ impl JmapClient {
         sort: Option<Value>,
         position: u32,
         limit: u32,
+        collapse_threads: bool,
     ) -> Result<Value> {
         let sort = sort.unwrap_or_else(|| json!([{"property": "receivedAt", "isAscending": false}]));
 
@@ -134,133 +732,1947 @@ impl JmapClient {
                 "filter": filter,
                 "sort": sort,
                 "position": position,
-                "limit": limit
-            }),
-        )
-        .await
-    }
-
-    pub async fn get_emails(&self, ids: &[String]) -> Result<Value> {
-        self.call(
-            "Email/get",
-            json!({
-                "accountId": self.account_id,
-                "#ids": { "resultOf": null, "name": null, "path": null },
-                "ids": ids,
-                "properties": [
-                    "id", "threadId", "mailboxIds", "from", "to", "cc", "bcc",
-                    "subject", "receivedAt", "sentAt", "size", "keywords",
-                    "preview", "textBody", "htmlBody", "bodyValues"
-                ],
-                "fetchTextBodyValues": true,
-                "fetchHTMLBodyValues": true,
-                "maxBodyValueBytes": 65536
+                "limit": limit,
+                "collapseThreads": collapse_threads
             }),
         )
         .await
     }
-    pub fn account_id(&self) -> &str {
-        &self.account_id
-    }
-
-    pub fn username(&self) -> &str {
-        &self.username
-    }
 
-    async fn get_drafts_mailbox_id(&self) -> Result<String> {
-        let result = self.get_mailboxes().await?;
-        result["list"]
-            .as_array()
-            .and_then(|list| {
-                list.iter().find(|m| m["role"].as_str() == Some("drafts"))
-            })
-            .and_then(|m| m["id"].as_str())
-            .map(|s| s.to_string())
-            .context("no drafts mailbox found")
+    /// Email/query chained with SearchSnippet/get, so each result comes with
+    /// the subject and preview fragments that matched, highlighted with
+    /// `<mark>`. Snippets are null for emails matched on non-text criteria.
+    /// With `calculate_total` the result includes the full match count.
+    pub async fn search_with_snippets(
+        &self,
+        filter: Value,
+        sort: Option<Value>,
+        position: u32,
+        limit: u32,
+        collapse_threads: bool,
+        calculate_total: bool,
+    ) -> Result<Value> {
+        let sort = sort.unwrap_or_else(|| json!([{"property": "receivedAt", "isAscending": false}]));
+        let results = self
+            .call_multi(vec![
+                (
+                    "Email/query",
+                    json!({
+                        "accountId": self.account_id,
+                        "filter": filter,
+                        "sort": sort,
+                        "position": position,
+                        "limit": limit,
+                        "collapseThreads": collapse_threads,
+                        "calculateTotal": calculate_total
+                    }),
+                    "q",
+                ),
+                (
+                    "SearchSnippet/get",
+                    json!({
+                        "accountId": self.account_id,
+                        "filter": filter,
+                        "#emailIds": {"resultOf": "q", "name": "Email/query", "path": "/ids"}
+                    }),
+                    "s",
+                ),
+            ])
+            .await?;
+        let mut results = results.into_iter();
+        let mut query = results.next().context("no Email/query response")?;
+        let snippets = results.next().context("no SearchSnippet/get response")?;
+        query["snippets"] = snippets["list"].clone();
+        Ok(query)
     }
 
-    async fn get_identity_id(&self) -> Result<String> {
-        let result = self.call("Identity/get", json!({"accountId": self.account_id})).await?;
-        result["list"]
-            .as_array()
-            .and_then(|list| list.first())
-            .and_then(|id| id["id"].as_str())
-            .map(|s| s.to_string())
-            .context("no identity found for this account")
+    /// Email/queryChanges for a query last seen at `since_query_state`: the
+    /// ids removed and added (with their new index). When the server can't
+    /// calculate the changes, the first `limit` results of a fresh query are
+    /// returned instead, marked `"fresh": true`.
+    pub async fn query_changes(
+        &self,
+        filter: Value,
+        sort: Option<Value>,
+        since_query_state: &str,
+        limit: u32,
+        collapse_threads: bool,
+    ) -> Result<Value> {
+        let sort = sort.unwrap_or_else(|| json!([{"property": "receivedAt", "isAscending": false}]));
+        let changes = self
+            .call(
+                "Email/queryChanges",
+                json!({
+                    "accountId": self.account_id,
+                    "filter": filter,
+                    "sort": sort,
+                    "sinceQueryState": since_query_state,
+                    "maxChanges": self.max_objects_in_get,
+                    "collapseThreads": collapse_threads
+                }),
+            )
+            .await;
+        match changes {
+            Ok(changes) => Ok(changes),
+            Err(e) if ["cannotCalculateChanges", "tooManyChanges"]
+                .iter()
+                .any(|kind| e.to_string().contains(kind)) =>
+            {
+                let mut fresh =
+                    self.search_emails(filter, Some(sort), 0, limit, collapse_threads).await?;
+                fresh["fresh"] = json!(true);
+                Ok(fresh)
+            }
+            Err(e) => Err(e),
+        }
     }
 
-    pub async fn send_email(
+    /// Email/query narrowed by attachment filename and/or MIME type, which
+    /// JMAP can't filter on directly: candidates are paged from the server
+    /// and their `attachments` checked in batches until `limit` matches are
+    /// found or `MAX_SCAN` candidates were examined. `next_position`
+    /// continues the scan on a later call.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn search_by_attachment(
         &self,
-        from: &str,
-        to: &[String],
-        subject: &str,
-        body: &str,
-        cc: &[String],
-        bcc: &[String],
+        filter: Value,
+        sort: Option<Value>,
+        position: u32,
+        limit: u32,
+        name: Option<&str>,
+        mime: Option<&str>,
+        collapse_threads: bool,
     ) -> Result<Value> {
-        let identity_id = self.get_identity_id().await?;
+        const PAGE: u32 = 50;
+        const MAX_SCAN: u32 = 1000;
+        let name = name.map(str::to_lowercase);
+        let mime = mime.map(str::to_lowercase);
 
-        let to_addrs: Vec<Value> = to.iter().map(|a| json!({"email": a})).collect();
-        let cc_addrs: Vec<Value> = cc.iter().map(|a| json!({"email": a})).collect();
-        let bcc_addrs: Vec<Value> = bcc.iter().map(|a| json!({"email": a})).collect();
+        let mut ids = Vec::new();
+        let mut next = position;
+        let mut exhausted = false;
 
-        let drafts_id = self.get_drafts_mailbox_id().await?;
+        while ids.len() < limit as usize && !exhausted && next - position < MAX_SCAN {
+            let page = self
+                .search_emails(filter.clone(), sort.clone(), next, PAGE, collapse_threads)
+                .await?;
+            let candidates: Vec<String> = page["ids"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|id| id.as_str().map(String::from))
+                .collect();
+            exhausted = candidates.len() < PAGE as usize;
 
-        let mut email = json!({
-            "from": [{"email": from}],
-            "to": to_addrs,
-            "subject": subject,
-            "bodyValues": {
-                "body": {
-                    "value": body,
-                    "charset": "utf-8"
+            let result = self
+                .call(
+                    "Email/get",
+                    json!({
+                        "accountId": self.account_id,
+                        "ids": candidates,
+                        "properties": ["id", "attachments"],
+                        "bodyProperties": ["name", "type"]
+                    }),
+                )
+                .await?;
+            let by_id: HashMap<&str, &Value> = result["list"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|e| Some((e["id"].as_str()?, &e["attachments"])))
+                .collect();
+
+            for id in &candidates {
+                next += 1;
+                let attachments = by_id.get(id.as_str()).and_then(|a| a.as_array());
+                let matched = attachments.into_iter().flatten().any(|a| {
+                    let a_name = a["name"].as_str().unwrap_or_default().to_lowercase();
+                    let a_type = a["type"].as_str().unwrap_or_default().to_lowercase();
+                    name.as_ref().is_none_or(|n| a_name.contains(n.as_str()))
+                        && mime.as_ref().is_none_or(|m| a_type.contains(m.as_str()))
+                });
+                if matched {
+                    ids.push(id.clone());
+                    if ids.len() == limit as usize {
+                        break;
+                    }
                 }
-            },
-            "textBody": [{"partId": "body", "type": "text/plain"}],
-            "mailboxIds": {drafts_id: true}
-        });
+            }
+        }
+
+        let done = exhausted && ids.len() < limit as usize;
+        Ok(json!({
+            "accountId": self.account_id,
+            "ids": ids,
+            "position": position,
+            "next_position": if done { Value::Null } else { json!(next) }
+        }))
+    }
+
+    /// Collects every email ID matching `filter`, paging through Email/query.
+    pub async fn query_all_ids(&self, filter: Value) -> Result<Vec<String>> {
+        const PAGE: u32 = 500;
+        let mut ids = Vec::new();
 
-        if !cc_addrs.is_empty() {
-            email["cc"] = json!(cc_addrs);
+        loop {
+            let result = self
+                .call(
+                    "Email/query",
+                    json!({
+                        "accountId": self.account_id,
+                        "filter": filter,
+                        "position": ids.len(),
+                        "limit": PAGE
+                    }),
+                )
+                .await?;
+            let page: Vec<String> = result["ids"]
+                .as_array()
+                .context("Email/query returned no ids")?
+                .iter()
+                .filter_map(|id| id.as_str().map(String::from))
+                .collect();
+            let done = page.len() < PAGE as usize;
+            ids.extend(page);
+            if done {
+                return Ok(ids);
+            }
         }
-        if !bcc_addrs.is_empty() {
-            email["bcc"] = json!(bcc_addrs);
+    }
+
+    /// Applies `patch` to every email in `ids` via Email/set update, in
+    /// chunks of `maxObjectsInSet`. Returns `(updated, failed)` ID lists.
+    pub async fn update_emails(
+        &self,
+        ids: &[String],
+        patch: Value,
+        progress: &Progress,
+    ) -> Result<(Vec<String>, Vec<Value>)> {
+        let mut updated = Vec::new();
+        let mut failed = Vec::new();
+        let total = ids.len() as u64;
+
+        for chunk in ids.chunks(self.max_objects_in_set) {
+            let update: serde_json::Map<String, Value> =
+                chunk.iter().map(|id| (id.clone(), patch.clone())).collect();
+            let result = self
+                .call("Email/set", json!({ "accountId": self.account_id, "update": update }))
+                .await?;
+            collect_set_outcome(&result, "updated", "notUpdated", &mut updated, &mut failed);
+            let done = (updated.len() + failed.len()) as u64;
+            progress.report(done, Some(total), format!("updated {done} of {total} emails"));
         }
 
-        let results = self.call_multi(vec![
-            (
-                "Email/set",
-                json!({
-                    "accountId": self.account_id,
-                    "create": {
-                        "draft": email
-                    }
-                }),
-                "r0",
-            ),
-            (
-                "EmailSubmission/set",
-                json!({
-                    "accountId": self.account_id,
-                    "create": {
-                        "send": {
-                            "emailId": "#draft",
-                            "identityId": identity_id
-                        }
-                    },
-                    "onSuccessDestroyEmail": ["#send"]
-                }),
-                "r1",
-            ),
-        ]).await?;
+        Ok((updated, failed))
+    }
 
-        // Return the submission result
-        results.into_iter().last().context("no submission response")
+    /// Sets (`on`) or clears a keyword such as `$seen` on `ids`. Returns
+    /// `(updated, failed)`.
+    pub async fn set_keyword(
+        &self,
+        ids: &[String],
+        keyword: &str,
+        on: bool,
+        progress: &Progress,
+    ) -> Result<(Vec<String>, Vec<Value>)> {
+        let mut patch = serde_json::Map::new();
+        patch.insert(format!("keywords/{keyword}"), if on { json!(true) } else { Value::Null });
+        self.update_emails(ids, Value::Object(patch), progress).await
     }
-}
 
-#[derive(Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct JmapResponse {
+    /// Files `ids` in `mailbox_id`, removing them from every other mailbox
+    /// unless `keep_existing` is set, in which case the mailbox is added
+    /// alongside their current ones. Returns `(updated, failed)`.
+    pub async fn move_emails(
+        &self,
+        ids: &[String],
+        mailbox_id: &str,
+        keep_existing: bool,
+        progress: &Progress,
+    ) -> Result<(Vec<String>, Vec<Value>)> {
+        let patch = if keep_existing {
+            let mut patch = serde_json::Map::new();
+            patch.insert(format!("mailboxIds/{mailbox_id}"), json!(true));
+            Value::Object(patch)
+        } else {
+            json!({ "mailboxIds": { mailbox_id: true } })
+        };
+        self.update_emails(ids, patch, progress).await
+    }
+
+    /// Permanently destroys `ids` via Email/set, in chunks of
+    /// `maxObjectsInSet`. Returns `(destroyed, failed)`.
+    pub async fn destroy_emails(
+        &self,
+        ids: &[String],
+        progress: &Progress,
+    ) -> Result<(Vec<String>, Vec<Value>)> {
+        let mut destroyed = Vec::new();
+        let mut failed = Vec::new();
+        let total = ids.len() as u64;
+
+        for chunk in ids.chunks(self.max_objects_in_set) {
+            let result = self
+                .call("Email/set", json!({ "accountId": self.account_id, "destroy": chunk }))
+                .await?;
+            collect_set_outcome(&result, "destroyed", "notDestroyed", &mut destroyed, &mut failed);
+            let done = (destroyed.len() + failed.len()) as u64;
+            progress.report(done, Some(total), format!("destroyed {done} of {total} emails"));
+        }
+
+        Ok((destroyed, failed))
+    }
+
+    /// ID of the mailbox with the given role, if the account has one.
+    /// Cached per account, since special mailboxes rarely change.
+    pub async fn mailbox_id_by_role(&self, role: &str) -> Result<Option<String>> {
+        let key = (self.account_id.clone(), role.to_string());
+        if let Some(id) = self.role_ids.lock().unwrap().get(&key) {
+            return Ok(Some(id.clone()));
+        }
+
+        let mailbox = self.find_mailbox_by_role(role).await?;
+        let id = mailbox.and_then(|m| m["id"].as_str().map(String::from));
+        if let Some(id) = &id {
+            self.role_ids.lock().unwrap().insert(key, id.clone());
+        }
+        Ok(id)
+    }
+
+    /// Resolves a mailbox given either its ID or its name (case-insensitive).
+    pub async fn resolve_mailbox(&self, mailbox: &str) -> Result<Value> {
+        let result = self.get_mailboxes().await?;
+        let list = result["list"].as_array().context("Mailbox/get returned no list")?;
+        list.iter()
+            .find(|m| m["id"].as_str() == Some(mailbox))
+            .or_else(|| {
+                list.iter().find(|m| {
+                    m["name"].as_str().is_some_and(|n| n.eq_ignore_ascii_case(mailbox))
+                })
+            })
+            .cloned()
+            .with_context(|| format!("no mailbox named or with id {mailbox:?}"))
+    }
+
+    /// Fetches only `properties` for `ids`, in chunks of `maxObjectsInGet`.
+    pub async fn get_email_properties(&self, ids: &[String], properties: &[&str]) -> Result<Vec<Value>> {
+        let mut emails = Vec::new();
+        for chunk in ids.chunks(self.max_objects_in_get) {
+            let result = self
+                .call(
+                    "Email/get",
+                    json!({
+                        "accountId": self.account_id,
+                        "ids": chunk,
+                        "properties": properties
+                    }),
+                )
+                .await?;
+            emails.extend(result["list"].as_array().cloned().unwrap_or_default());
+        }
+        Ok(emails)
+    }
+
+    /// Returns the ID of the mailbox at `path` (e.g. "Archive/2023"), taken
+    /// from the top level or from mailbox `under`, creating any missing
+    /// folders in one Mailbox/set call whose creates reference each other's
+    /// creation IDs.
+    pub async fn ensure_mailbox_path(&self, under: Option<&str>, path: &str) -> Result<String> {
+        let segments: Vec<&str> = path.split('/').map(str::trim).filter(|s| !s.is_empty()).collect();
+        if segments.is_empty() {
+            bail!("mailbox path must not be empty");
+        }
+
+        let mailboxes = self.get_mailboxes().await?;
+        let list = mailboxes["list"].as_array().cloned().unwrap_or_default();
+
+        let mut parent: Option<String> = under.map(String::from);
+        let mut depth = 0;
+        while depth < segments.len() {
+            let found = list.iter().find(|m| {
+                m["name"].as_str() == Some(segments[depth]) && m["parentId"].as_str() == parent.as_deref()
+            });
+            match found.and_then(|m| m["id"].as_str()) {
+                Some(id) => parent = Some(id.to_string()),
+                None => break,
+            }
+            depth += 1;
+        }
+        if depth == segments.len() {
+            return parent.context("mailbox path resolved to nothing");
+        }
+
+        let mut create = serde_json::Map::new();
+        for (i, name) in segments[depth..].iter().enumerate() {
+            let parent_ref = if i == 0 {
+                parent.clone().map(Value::String).unwrap_or(Value::Null)
+            } else {
+                json!(format!("#m{}", i - 1))
+            };
+            create.insert(format!("m{i}"), json!({ "name": name, "parentId": parent_ref }));
+        }
+        let leaf = format!("m{}", segments.len() - depth - 1);
+
+        let result = self
+            .call("Mailbox/set", json!({ "accountId": self.account_id, "create": create }))
+            .await?;
+        if let Some(errors) = result["notCreated"].as_object()
+            && let Some((key, err)) = errors.iter().next()
+        {
+            bail!("failed to create mailbox {key} for {path:?}: {err}");
+        }
+        result["created"][&leaf]["id"]
+            .as_str()
+            .map(String::from)
+            .with_context(|| format!("server did not return an id for {path:?}"))
+    }
+
+    /// Current Email state string, for later use with Email/changes.
+    pub async fn email_state(&self) -> Result<String> {
+        let result = self
+            .call("Email/get", json!({ "accountId": self.account_id, "ids": [] }))
+            .await?;
+        result["state"].as_str().map(String::from).context("Email/get returned no state")
+    }
+
+    pub async fn email_changes(&self, since_state: &str) -> Result<Value> {
+        self.call(
+            "Email/changes",
+            json!({
+                "accountId": self.account_id,
+                "sinceState": since_state,
+                "maxChanges": self.max_objects_in_get
+            }),
+        )
+        .await
+    }
+
+    /// Current Mailbox state string, for later use with Mailbox/changes.
+    pub async fn mailbox_state(&self) -> Result<String> {
+        let result = self
+            .call("Mailbox/get", json!({ "accountId": self.account_id, "ids": [] }))
+            .await?;
+        result["state"].as_str().map(String::from).context("Mailbox/get returned no state")
+    }
+
+    /// Mailboxes created, updated (including their counts) or destroyed
+    /// since `since_state`.
+    pub async fn mailbox_changes(&self, since_state: &str) -> Result<Value> {
+        self.call(
+            "Mailbox/changes",
+            json!({
+                "accountId": self.account_id,
+                "sinceState": since_state,
+                "maxChanges": self.max_objects_in_get
+            }),
+        )
+        .await
+    }
+
+    /// Runs Email/query and fetches summary fields for the matches in a
+    /// single request, using a back-reference to the query's ids.
+    pub async fn search_summaries(&self, filter: Value, limit: u32) -> Result<Vec<Value>> {
+        let results = self
+            .call_multi(vec![
+                (
+                    "Email/query",
+                    json!({
+                        "accountId": self.account_id,
+                        "filter": filter,
+                        "sort": [{"property": "receivedAt", "isAscending": false}],
+                        "limit": limit
+                    }),
+                    "q",
+                ),
+                (
+                    "Email/get",
+                    json!({
+                        "accountId": self.account_id,
+                        "#ids": { "resultOf": "q", "name": "Email/query", "path": "/ids" },
+                        "properties": ["id", "threadId", "from", "subject", "receivedAt", "preview", "keywords"]
+                    }),
+                    "g",
+                ),
+            ])
+            .await?;
+
+        let list = results
+            .into_iter()
+            .nth(1)
+            .context("no Email/get response")?["list"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        Ok(list)
+    }
+
+    /// Runs Email/query and fetches the full matching emails, as
+    /// `get_emails` returns them, in a single request. The result is the
+    /// Email/get response plus the query's `queryState`, `position` and
+    /// `total`.
+    pub async fn search_and_get(
+        &self,
+        filter: Value,
+        sort: Option<Value>,
+        position: u32,
+        limit: u32,
+        collapse_threads: bool,
+    ) -> Result<Value> {
+        let sort = sort.unwrap_or_else(|| json!([{"property": "receivedAt", "isAscending": false}]));
+        let results = self
+            .call_multi(vec![
+                (
+                    "Email/query",
+                    json!({
+                        "accountId": self.account_id,
+                        "filter": filter,
+                        "sort": sort,
+                        "position": position,
+                        "limit": limit,
+                        "collapseThreads": collapse_threads,
+                        "calculateTotal": true
+                    }),
+                    "q",
+                ),
+                (
+                    "Email/get",
+                    json!({
+                        "accountId": self.account_id,
+                        "#ids": { "resultOf": "q", "name": "Email/query", "path": "/ids" },
+                        "properties": full_properties(),
+                        "fetchTextBodyValues": true,
+                        "fetchHTMLBodyValues": true,
+                        "maxBodyValueBytes": MAX_BODY_VALUE_BYTES
+                    }),
+                    "g",
+                ),
+            ])
+            .await?;
+
+        let mut results = results.into_iter();
+        let query = results.next().context("no Email/query response")?;
+        let mut result = results.next().context("no Email/get response")?;
+        for key in ["queryState", "position", "total"] {
+            result[key] = query[key].clone();
+        }
+        mark_truncated(&mut result);
+        html::convert_text_bodies(&mut result);
+        headers::annotate(&mut result);
+        self.expand_tnef(&mut result).await;
+        Ok(result)
+    }
+
+    /// Fetches emails in full, or with only `properties` (plus `id` and the
+    /// headers [`headers::annotate`] reads). Body values are fetched only
+    /// when `bodyValues` is among the properties, each cut at
+    /// `max_body_bytes`; see [`mark_truncated`].
+    pub async fn get_emails(
+        &self,
+        ids: &[String],
+        properties: Option<&[String]>,
+        max_body_bytes: Option<u32>,
+    ) -> Result<Value> {
+        let properties: Vec<&str> = match properties {
+            Some(requested) => {
+                let mut properties = vec!["id"];
+                for property in requested.iter().map(|p| p.trim()) {
+                    if !property.is_empty() && !properties.contains(&property) {
+                        properties.push(property);
+                    }
+                }
+                properties.extend_from_slice(headers::PROPERTIES);
+                properties
+            }
+            None => full_properties(),
+        };
+        let bodies = properties.contains(&"bodyValues");
+
+        let mut result = self
+            .call(
+                "Email/get",
+                json!({
+                    "accountId": self.account_id,
+                    "#ids": { "resultOf": null, "name": null, "path": null },
+                    "ids": ids,
+                    "properties": properties,
+                    "fetchTextBodyValues": bodies,
+                    "fetchHTMLBodyValues": bodies,
+                    "maxBodyValueBytes": max_body_bytes.unwrap_or(MAX_BODY_VALUE_BYTES)
+                }),
+            )
+            .await?;
+        mark_truncated(&mut result);
+        html::convert_text_bodies(&mut result);
+
+        headers::annotate(&mut result);
+        self.expand_tnef(&mut result).await;
+        Ok(result)
+    }
+
+    /// IDs of the emails in a thread, oldest first. `id` may be a thread ID
+    /// or the ID of any email in the thread.
+    pub async fn thread_email_ids(&self, id: &str) -> Result<Vec<String>> {
+        let thread_id = match self.get_thread(id).await? {
+            Some(ids) => return Ok(ids),
+            None => {
+                let emails = self.get_email_properties(&[id.to_string()], &["threadId"]).await?;
+                emails
+                    .first()
+                    .and_then(|e| e["threadId"].as_str())
+                    .map(String::from)
+                    .with_context(|| format!("no thread or email with id {id:?}"))?
+            }
+        };
+        self.get_thread(&thread_id)
+            .await?
+            .with_context(|| format!("thread {thread_id} not found"))
+    }
+
+    async fn get_thread(&self, thread_id: &str) -> Result<Option<Vec<String>>> {
+        let result = self
+            .call("Thread/get", json!({ "accountId": self.account_id, "ids": [thread_id] }))
+            .await?;
+        Ok(result["list"][0]["emailIds"].as_array().map(|ids| {
+            ids.iter().filter_map(|id| id.as_str().map(String::from)).collect()
+        }))
+    }
+
+    /// Attachment parts of `ids`, without bodies, in chunks of
+    /// `maxObjectsInGet`. TNEF containers are expanded as in
+    /// [`Self::get_emails`].
+    pub async fn get_attachments(&self, ids: &[String]) -> Result<Value> {
+        let mut list = Vec::new();
+        for chunk in ids.chunks(self.max_objects_in_get) {
+            let result = self
+                .call(
+                    "Email/get",
+                    json!({
+                        "accountId": self.account_id,
+                        "ids": chunk,
+                        "properties": ["id", "subject", "attachments"],
+                        "bodyProperties": [
+                            "partId", "blobId", "name", "type", "size", "disposition", "cid"
+                        ]
+                    }),
+                )
+                .await?;
+            list.extend(result["list"].as_array().cloned().unwrap_or_default());
+        }
+        let mut result = json!({ "list": list });
+        self.expand_tnef(&mut result).await;
+        Ok(result)
+    }
+
+    /// Parses an uploaded RFC 5322 blob with Email/parse, including body
+    /// values, without storing it as an email. A message extracted from a
+    /// `winmail.dat` isn't on the server, so it is parsed locally.
+    pub async fn parse_email(&self, blob_id: &str) -> Result<Value> {
+        if tnef::split_blob_id(blob_id).is_some() {
+            let raw = self.download_blob(blob_id, "message.eml", "message/rfc822").await?;
+            return Ok(mime::parse(&raw));
+        }
+        let result = self
+            .call(
+                "Email/parse",
+                json!({
+                    "accountId": self.account_id,
+                    "blobIds": [blob_id],
+                    "properties": [
+                        "from", "to", "cc", "replyTo", "subject", "sentAt", "messageId",
+                        "textBody", "htmlBody", "attachments", "bodyValues"
+                    ],
+                    "fetchTextBodyValues": true,
+                    "fetchHTMLBodyValues": true,
+                    "maxBodyValueBytes": MAX_BODY_VALUE_BYTES
+                }),
+            )
+            .await?;
+
+        match result["parsed"].get(blob_id) {
+            Some(email) => Ok(email.clone()),
+            None => bail!("server could not parse blob {blob_id} as a message"),
+        }
+    }
+
+    /// Replaces `winmail.dat` attachments with the files they contain. Each
+    /// file gets a [`tnef::blob_id`] that [`Self::download_blob`] decodes
+    /// on demand, so nothing is stored on the server; a container that
+    /// fails to decode is left in place.
+    async fn expand_tnef(&self, result: &mut Value) {
+        let Some(list) = result["list"].as_array_mut() else {
+            return;
+        };
+        for email in list {
+            let Some(attachments) = email["attachments"].as_array() else {
+                continue;
+            };
+            if !attachments.iter().any(|a| {
+                tnef::is_tnef(
+                    a["type"].as_str().unwrap_or_default(),
+                    a["name"].as_str().unwrap_or_default(),
+                )
+            }) {
+                continue;
+            }
+
+            let mut expanded = Vec::new();
+            for a in attachments {
+                let name = a["name"].as_str().unwrap_or_default();
+                if tnef::is_tnef(a["type"].as_str().unwrap_or_default(), name)
+                    && let Some(blob_id) = a["blobId"].as_str()
+                {
+                    match self.unpack_tnef(blob_id, name).await {
+                        Ok(files) => {
+                            expanded.extend(files);
+                            continue;
+                        }
+                        Err(e) => tracing::warn!(%blob_id, "failed to decode TNEF attachment: {e:#}"),
+                    }
+                }
+                expanded.push(a.clone());
+            }
+            email["attachments"] = json!(expanded);
+        }
+    }
+
+    async fn unpack_tnef(&self, blob_id: &str, name: &str) -> Result<Vec<Value>> {
+        let data = self.download_blob(blob_id, name, tnef::CONTENT_TYPE).await?;
+        let mut files = Vec::new();
+        for (i, file) in tnef::decode(&data)?.into_iter().enumerate() {
+            let content_type = file
+                .content_type
+                .unwrap_or_else(|| "application/octet-stream".to_string());
+            files.push(json!({
+                "name": file.name,
+                "type": content_type,
+                "size": file.data.len(),
+                "blobId": tnef::blob_id(blob_id, i),
+                "disposition": "attachment",
+                "extracted_from": name
+            }));
+        }
+        Ok(files)
+    }
+
+    pub fn account_id(&self) -> &str {
+        &self.account_id
+    }
+
+    /// Opens the session's EventSource stream of Mailbox and Email state
+    /// changes, with a ping every `ping` to show the connection is alive.
+    /// `last_event_id` asks the server to resume after that event. None
+    /// when the server offers no push.
+    pub async fn open_event_source(
+        &self,
+        last_event_id: Option<&str>,
+        ping: Duration,
+    ) -> Result<Option<reqwest::Response>> {
+        let Some(template) = &self.event_source_url else {
+            return Ok(None);
+        };
+        let url = template
+            .replace("{types}", &url_escape("Mailbox,Email"))
+            .replace("{closeafter}", "no")
+            .replace("{ping}", &ping.as_secs().to_string());
+        tracing::debug!(%url, ?last_event_id, "opening EventSource");
+
+        let mut request = self
+            .http
+            .get(&url)
+            .basic_auth(&self.username, Some(&self.password))
+            .header(reqwest::header::ACCEPT, "text/event-stream");
+        if let Some(id) = last_event_id {
+            request = request.header("Last-Event-ID", id);
+        }
+        let resp = request
+            .send()
+            .await
+            .context("EventSource connection failed")?
+            .error_for_status()
+            .context("EventSource connection rejected")?;
+        Ok(Some(resp))
+    }
+
+    /// Total attachment size the current account allows per email, if the
+    /// server advertises one.
+    pub fn attachment_limit(&self) -> Option<u64> {
+        self.attachment_limits.get(&self.account_id).copied()
+    }
+
+    /// Fails when attachments totalling `total` bytes exceed the per-email
+    /// limit the server advertises for this account.
+    pub fn check_attachment_size(&self, total: u64) -> Result<()> {
+        if let Some(limit) = self.attachment_limit()
+            && total > limit
+        {
+            bail!(
+                "attachments total {}, over this account's {} limit per email; share large \
+                 files through a file-storage link instead",
+                format_size(total),
+                format_size(limit)
+            );
+        }
+        Ok(())
+    }
+
+    /// An explicit submission envelope for `draft` when it needs SMTP
+    /// parameters (scheduled delivery or DSN requests); `None` lets the
+    /// server derive one from the headers.
+    fn envelope(&self, draft: &Draft) -> Result<Option<Value>> {
+        let dsn = !draft.notify.is_empty() || draft.return_content.is_some();
+        if draft.send_at.is_none() && !dsn {
+            return Ok(None);
+        }
+        if dsn && !self.dsn_accounts.contains(&self.account_id) {
+            bail!("this server does not support delivery status notifications (DSN)");
+        }
+
+        let mut mail_from = serde_json::Map::new();
+        if let Some(at) = draft.send_at {
+            self.check_send_at(at)?;
+            mail_from.insert("HOLDUNTIL".into(), json!(at.to_string()));
+        }
+        if let Some(ret) = &draft.return_content {
+            mail_from.insert("RET".into(), json!(ret));
+        }
+        let rcpt_params = if draft.notify.is_empty() {
+            Value::Null
+        } else {
+            json!({ "NOTIFY": draft.notify.join(",") })
+        };
+        let rcpt_to: Vec<Value> = draft
+            .to
+            .iter()
+            .chain(&draft.cc)
+            .chain(&draft.bcc)
+            .map(|a| json!({ "email": a, "parameters": rcpt_params }))
+            .collect();
+        Ok(Some(json!({
+            "mailFrom": { "email": draft.from, "parameters": mail_from },
+            "rcptTo": rcpt_to
+        })))
+    }
+
+    /// Fails unless the server can hold a submission until `at`.
+    fn check_send_at(&self, at: jiff::Timestamp) -> Result<()> {
+        let Some(&max) = self.delayed_send_limits.get(&self.account_id) else {
+            bail!("this server does not support scheduled sending (FUTURERELEASE)");
+        };
+        let now = jiff::Timestamp::now();
+        if at <= now {
+            bail!("send_at {at} is in the past");
+        }
+        let latest = now + jiff::SignedDuration::from_secs(max as i64);
+        if at > latest {
+            bail!("send_at {at} is too far ahead; the server holds mail until {latest} at most");
+        }
+        Ok(())
+    }
+
+    pub fn account_name(&self) -> &str {
+        self.accounts.get(&self.account_id).map(String::as_str).unwrap_or(&self.account_id)
+    }
+
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    async fn get_drafts_mailbox_id(&self) -> Result<String> {
+        self.mailbox_id_by_role("drafts")
+            .await?
+            .context("no drafts mailbox found")
+    }
+
+    async fn get_trash_mailbox_id(&self) -> Result<String> {
+        self.mailbox_id_by_role("trash")
+            .await?
+            .context("no trash mailbox found")
+    }
+
+    /// Creates a mailbox, nested under `parent_id` when given, and returns
+    /// its ID.
+    pub async fn create_mailbox(&self, name: &str, parent_id: Option<&str>) -> Result<String> {
+        let result = self
+            .call(
+                "Mailbox/set",
+                json!({
+                    "accountId": self.account_id,
+                    "create": { "mailbox": { "name": name, "parentId": parent_id } }
+                }),
+            )
+            .await?;
+        created_id(&result, "mailbox")
+    }
+
+    /// Applies a Mailbox/set `patch` (e.g. `name`, `parentId`) to one
+    /// mailbox.
+    pub async fn update_mailbox(&self, id: &str, patch: Value) -> Result<()> {
+        let result = self
+            .call("Mailbox/set", json!({ "accountId": self.account_id, "update": { id: patch } }))
+            .await?;
+        if let Some(err) = result["notUpdated"].get(id) {
+            bail!("server rejected update of mailbox {id}: {}", set_error_text(err));
+        }
+        Ok(())
+    }
+
+    /// Destroys a mailbox. Unless `remove_emails` is set the server refuses
+    /// when it still holds mail (`mailboxHasEmail`).
+    pub async fn destroy_mailbox(&self, id: &str, remove_emails: bool) -> Result<()> {
+        let result = self
+            .call(
+                "Mailbox/set",
+                json!({
+                    "accountId": self.account_id,
+                    "destroy": [id],
+                    "onDestroyRemoveEmails": remove_emails
+                }),
+            )
+            .await?;
+        self.role_ids.lock().unwrap().retain(|_, cached| cached != id);
+        if let Some(err) = result["notDestroyed"].get(id) {
+            if err["type"] == "mailboxHasEmail" {
+                bail!("mailbox {id} is not empty; set remove_emails to delete its mail too");
+            }
+            bail!("server rejected deletion of mailbox {id}: {}", set_error_text(err));
+        }
+        Ok(())
+    }
+
+    /// The mailbox with the `archive` role, falling back to a top-level
+    /// "Archive" folder, which is created if missing.
+    pub async fn archive_mailbox_id(&self) -> Result<String> {
+        match self.mailbox_id_by_role("archive").await? {
+            Some(id) => Ok(id),
+            None => self.ensure_mailbox_path(None, "Archive").await,
+        }
+    }
+
+    /// Moves `ids` to Trash, or with `permanent` destroys them outright.
+    /// Returns `(done, failed)`.
+    pub async fn delete_emails(
+        &self,
+        ids: &[String],
+        permanent: bool,
+        progress: &Progress,
+    ) -> Result<(Vec<String>, Vec<Value>)> {
+        if permanent {
+            return self.destroy_emails(ids, progress).await;
+        }
+        let trash = self.get_trash_mailbox_id().await?;
+        self.move_emails(ids, &trash, false, progress).await
+    }
+
+    /// The identity to send as by default: the one whose address is the
+    /// login, else the first.
+    async fn get_identity_id(&self) -> Result<String> {
+        let result = self.get_identities().await?;
+        let list = result["list"].as_array().context("Identity/get returned no list")?;
+        list.iter()
+            .find(|id| id["email"].as_str().is_some_and(|e| e.eq_ignore_ascii_case(&self.username)))
+            .or_else(|| list.first())
+            .and_then(|id| id["id"].as_str())
+            .map(|s| s.to_string())
+            .context("no identity found for this account")
+    }
+
+    pub async fn get_identities(&self) -> Result<Value> {
+        self.call("Identity/get", json!({"accountId": self.account_id})).await
+    }
+
+    /// Creates a sending identity from an Identity object and returns its ID.
+    pub async fn create_identity(&self, identity: Value) -> Result<String> {
+        let result = self
+            .call(
+                "Identity/set",
+                json!({ "accountId": self.account_id, "create": { "identity": identity } }),
+            )
+            .await?;
+        created_id(&result, "identity")
+    }
+
+    /// Applies an Identity/set `patch` to one identity.
+    pub async fn update_identity(&self, id: &str, patch: Value) -> Result<()> {
+        let result = self
+            .call("Identity/set", json!({ "accountId": self.account_id, "update": { id: patch } }))
+            .await?;
+        if let Some(err) = result["notUpdated"].get(id) {
+            bail!("server rejected update of identity {id}: {}", set_error_text(err));
+        }
+        Ok(())
+    }
+
+    /// Resolves a sending identity given either its ID or its email
+    /// address (case-insensitive).
+    pub async fn resolve_identity(&self, identity: &str) -> Result<Value> {
+        let result = self.get_identities().await?;
+        let list = result["list"].as_array().context("Identity/get returned no list")?;
+        list.iter()
+            .find(|i| i["id"].as_str() == Some(identity))
+            .or_else(|| {
+                list.iter().find(|i| {
+                    i["email"].as_str().is_some_and(|e| e.eq_ignore_ascii_case(identity))
+                })
+            })
+            .cloned()
+            .with_context(|| format!("no identity with id or address {identity:?}"))
+    }
+
+    /// Addresses of the account's sending identities and its login, used to
+    /// keep the user out of reply-all recipients.
+    pub async fn identity_emails(&self) -> Result<Vec<String>> {
+        let result = self.get_identities().await?;
+        let mut emails = strings_at(&result["list"], "email");
+        emails.push(self.username.clone());
+        Ok(emails)
+    }
+
+    /// Fetches the email being replied to or forwarded, with its text body,
+    /// threading headers and attachments.
+    pub async fn get_original(&self, id: &str) -> Result<Value> {
+        let result = self
+            .call(
+                "Email/get",
+                json!({
+                    "accountId": self.account_id,
+                    "ids": [id],
+                    "properties": [
+                        "id", "from", "to", "cc", "replyTo", "subject", "sentAt",
+                        "receivedAt", "messageId", "references", "preview",
+                        "textBody", "bodyValues", "attachments"
+                    ],
+                    "fetchTextBodyValues": true
+                }),
+            )
+            .await?;
+        let mut email = result["list"]
+            .as_array()
+            .and_then(|list| list.first())
+            .cloned()
+            .with_context(|| format!("email {id} not found"))?;
+        html::convert_email(&mut email);
+        Ok(email)
+    }
+
+    /// The RFC 5322 source of an email, downloaded through its blobId.
+    pub async fn raw_email(&self, id: &str) -> Result<Vec<u8>> {
+        let result = self
+            .call(
+                "Email/get",
+                json!({ "accountId": self.account_id, "ids": [id], "properties": ["blobId"] }),
+            )
+            .await?;
+        let blob_id = result["list"][0]["blobId"]
+            .as_str()
+            .with_context(|| format!("email {id} not found"))?;
+        self.download_blob(blob_id, &format!("{id}.eml"), "message/rfc822").await
+    }
+
+    pub async fn send_email(
+        &self,
+        from: &str,
+        to: &[String],
+        subject: &str,
+        body: &str,
+        cc: &[String],
+        bcc: &[String],
+    ) -> Result<Value> {
+        let draft = Draft {
+            from: from.to_string(),
+            to: to.to_vec(),
+            cc: cc.to_vec(),
+            bcc: bcc.to_vec(),
+            subject: subject.to_string(),
+            body: body.to_string(),
+            ..Default::default()
+        };
+        self.send_draft(&draft).await
+    }
+
+    /// Stores `draft` and submits it in a single request.
+    pub async fn send_draft(&self, draft: &Draft) -> Result<Value> {
+        let mut send = json!({ "emailId": "#draft" });
+        if let Some(envelope) = self.envelope(draft)? {
+            send["envelope"] = envelope;
+        }
+        send["identityId"] = match &draft.identity_id {
+            Some(id) => json!(id),
+            None => json!(self.get_identity_id().await?),
+        };
+        let drafts_id = self.get_drafts_mailbox_id().await?;
+        let email = draft.to_email(&drafts_id);
+
+        let results = self.call_multi(vec![
+            (
+                "Email/set",
+                json!({
+                    "accountId": self.account_id,
+                    "create": {
+                        "draft": email
+                    }
+                }),
+                "r0",
+            ),
+            ("EmailSubmission/set", self.submission(send).await?, "r1"),
+        ]).await?;
+
+        created_id(&results[0], "draft")?;
+        let submission = results.into_iter().nth(1).context("no EmailSubmission/set response")?;
+        with_submission_id(submission)
+    }
+
+    /// Stores `draft` in the Drafts mailbox and returns its email ID.
+    pub async fn create_draft(&self, draft: &Draft) -> Result<String> {
+        let drafts_id = self.get_drafts_mailbox_id().await?;
+        let result = self
+            .call(
+                "Email/set",
+                json!({
+                    "accountId": self.account_id,
+                    "create": { "draft": draft.to_email(&drafts_id) }
+                }),
+            )
+            .await?;
+        created_id(&result, "draft")
+    }
+
+    /// Reads back a draft stored by [`Self::create_draft`].
+    pub async fn get_draft(&self, id: &str) -> Result<Draft> {
+        let result = self
+            .call(
+                "Email/get",
+                json!({
+                    "accountId": self.account_id,
+                    "ids": [id],
+                    "properties": [
+                        "from", "to", "cc", "bcc", "subject", "keywords",
+                        "inReplyTo", "references", "textBody", "htmlBody", "bodyValues",
+                        "attachments"
+                    ],
+                    "fetchTextBodyValues": true,
+                    "fetchHTMLBodyValues": true
+                }),
+            )
+            .await?;
+        let email = result["list"]
+            .as_array()
+            .and_then(|list| list.first())
+            .with_context(|| format!("draft {id} not found"))?;
+        if email["keywords"]["$draft"].as_bool() != Some(true) {
+            bail!("email {id} is not a draft");
+        }
+
+        let addresses = |field: &str| -> Vec<String> {
+            email[field]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|a| a["email"].as_str().map(String::from))
+                .collect()
+        };
+        let body_text = |field: &str, mime: Option<&str>| -> String {
+            email[field]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter(|part| mime.is_none_or(|m| part["type"].as_str() == Some(m)))
+                .filter_map(|part| part["partId"].as_str())
+                .filter_map(|part| email["bodyValues"][part]["value"].as_str())
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        let body = body_text("textBody", None);
+        // For a text-only message htmlBody lists the text/plain part.
+        let html = body_text("htmlBody", Some("text/html"));
+
+        Ok(Draft {
+            from: addresses("from").into_iter().next().unwrap_or_default(),
+            from_name: email["from"][0]["name"].as_str().map(String::from),
+            identity_id: None,
+            to: addresses("to"),
+            cc: addresses("cc"),
+            bcc: addresses("bcc"),
+            subject: email["subject"].as_str().unwrap_or_default().to_string(),
+            body,
+            html_body: (!html.is_empty()).then_some(html),
+            send_at: None,
+            notify: Vec::new(),
+            return_content: None,
+            in_reply_to: strings(&email["inReplyTo"]),
+            references: strings(&email["references"]),
+            attachments: email["attachments"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(attachment_ref)
+                .collect(),
+        })
+    }
+
+    /// Replaces draft `id` with `draft` (emails are immutable in JMAP) and
+    /// returns the new email ID. The old draft is only destroyed if the new
+    /// one was stored.
+    pub async fn replace_draft(&self, id: &str, draft: &Draft) -> Result<String> {
+        let new_id = self.create_draft(draft).await?;
+        let result = self
+            .call("Email/set", json!({ "accountId": self.account_id, "destroy": [id] }))
+            .await?;
+        if let Some(err) = result["notDestroyed"].get(id) {
+            tracing::warn!(%id, "failed to remove superseded draft: {err}");
+        }
+        Ok(new_id)
+    }
+
+    /// Submits an existing draft for delivery under the identity matching
+    /// its From address; once the submission succeeds the message is filed
+    /// as sent, unless `file_as_sent` is false, in which case it stays where
+    /// it is.
+    pub async fn submit_draft(&self, id: &str, file_as_sent: bool) -> Result<Value> {
+        let identity_id = self.draft_identity_id(id).await?;
+        let mut args = self
+            .submission(json!({ "emailId": id, "identityId": identity_id }))
+            .await?;
+        if !file_as_sent && let Some(args) = args.as_object_mut() {
+            args.remove("onSuccessUpdateEmail");
+            args.remove("onSuccessDestroyEmail");
+        }
+        with_submission_id(self.call("EmailSubmission/set", args).await?)
+    }
+
+    /// The ID of the identity whose address matches the From of draft `id`,
+    /// either exactly or through a `*@domain` wildcard identity.
+    async fn draft_identity_id(&self, id: &str) -> Result<String> {
+        let emails = self.get_email_properties(&[id.to_string()], &["from"]).await?;
+        let email = emails.first().with_context(|| format!("draft {id} not found"))?;
+        let from = email["from"][0]["email"]
+            .as_str()
+            .with_context(|| format!("draft {id} has no From address"))?;
+        let domain = from.rsplit_once('@').map(|(_, domain)| domain);
+
+        let result = self.get_identities().await?;
+        let list = result["list"].as_array().context("Identity/get returned no list")?;
+        list.iter()
+            .find(|i| i["email"].as_str().is_some_and(|e| e.eq_ignore_ascii_case(from)))
+            .or_else(|| {
+                list.iter().find(|i| {
+                    i["email"]
+                        .as_str()
+                        .and_then(|e| e.strip_prefix("*@"))
+                        .is_some_and(|d| domain.is_some_and(|domain| d.eq_ignore_ascii_case(domain)))
+                })
+            })
+            .and_then(|i| i["id"].as_str())
+            .map(String::from)
+            .with_context(|| format!("no identity matches the draft's From address {from}"))
+    }
+
+    /// EmailSubmission/set arguments creating the submission `send`. On
+    /// success the message moves from Drafts to Sent, where the duplicate
+    /// check looks for it; without a Sent mailbox the draft is removed.
+    async fn submission(&self, send: Value) -> Result<Value> {
+        let mut args = json!({
+            "accountId": self.account_id,
+            "create": { "send": send }
+        });
+
+        let mailboxes = self.get_mailboxes().await?;
+        let role_id = |role: &str| {
+            mailboxes["list"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .find(|m| m["role"].as_str() == Some(role))
+                .and_then(|m| m["id"].as_str())
+                .map(String::from)
+        };
+        match (role_id("drafts"), role_id("sent")) {
+            (Some(drafts), Some(sent)) => {
+                let mut patch = serde_json::Map::new();
+                patch.insert(format!("mailboxIds/{drafts}"), Value::Null);
+                patch.insert(format!("mailboxIds/{sent}"), json!(true));
+                patch.insert("keywords/$draft".into(), Value::Null);
+                args["onSuccessUpdateEmail"] = json!({ "#send": patch });
+            }
+            _ => args["onSuccessDestroyEmail"] = json!(["#send"]),
+        }
+        Ok(args)
+    }
+
+    /// A message sent within the last `window` to exactly `recipients`
+    /// (in any order, case-insensitive) with this `subject`, if any.
+    pub async fn find_recent_duplicate(
+        &self,
+        recipients: &[String],
+        subject: &str,
+        window: jiff::SignedDuration,
+    ) -> Result<Option<Value>> {
+        let Some(sent_id) = self.mailbox_id_by_role("sent").await? else {
+            return Ok(None);
+        };
+        let since = jiff::Timestamp::now() - window;
+        let filter = json!({
+            "operator": "AND",
+            "conditions": [
+                {"inMailbox": sent_id},
+                {"after": since.to_string()},
+                {"subject": subject}
+            ]
+        });
+        let found = self.search_emails(filter, None, 0, 20, false).await?;
+        let ids: Vec<String> = found["ids"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|id| id.as_str().map(String::from))
+            .collect();
+        if ids.is_empty() {
+            return Ok(None);
+        }
+
+        let normalize = |list: Vec<String>| {
+            let mut list: Vec<String> =
+                list.iter().map(|a| a.trim().to_ascii_lowercase()).collect();
+            list.sort();
+            list.dedup();
+            list
+        };
+        let wanted = normalize(recipients.to_vec());
+        let emails = self
+            .get_email_properties(&ids, &["id", "to", "cc", "bcc", "subject", "sentAt"])
+            .await?;
+        Ok(emails.into_iter().find(|email| {
+            let sent_to: Vec<String> = ["to", "cc", "bcc"]
+                .iter()
+                .flat_map(|f| email[*f].as_array().cloned().unwrap_or_default())
+                .filter_map(|a| a["email"].as_str().map(String::from))
+                .collect();
+            email["subject"].as_str() == Some(subject) && normalize(sent_to) == wanted
+        }))
+    }
+
+    /// Submits a complete RFC 5322 message exactly as given: it is uploaded,
+    /// imported into Drafts and sent to `recipients`, which (unlike the
+    /// headers) include any Bcc addresses.
+    pub async fn send_raw(
+        &self,
+        raw: Vec<u8>,
+        from: &str,
+        recipients: &[String],
+        progress: &Progress,
+    ) -> Result<Value> {
+        let identity_id = self.get_identity_id().await?;
+        let drafts_id = self.get_drafts_mailbox_id().await?;
+        let blob = self.upload_blob_with_progress(raw, "message/rfc822", progress).await?;
+        let rcpt_to: Vec<Value> = recipients.iter().map(|a| json!({"email": a})).collect();
+
+        let results = self
+            .call_multi(vec![
+                (
+                    "Email/import",
+                    json!({
+                        "accountId": self.account_id,
+                        "emails": {
+                            "draft": {
+                                "blobId": blob["blobId"],
+                                "mailboxIds": {drafts_id: true},
+                                "keywords": {"$draft": true, "$seen": true}
+                            }
+                        }
+                    }),
+                    "i",
+                ),
+                (
+                    "EmailSubmission/set",
+                    self.submission(json!({
+                        "emailId": "#draft",
+                        "identityId": identity_id,
+                        "envelope": {
+                            "mailFrom": {"email": from},
+                            "rcptTo": rcpt_to
+                        }
+                    }))
+                    .await?,
+                    "s",
+                ),
+            ])
+            .await?;
+
+        created_id(&results[0], "draft")?;
+        let submission = results.into_iter().nth(1).context("no EmailSubmission/set response")?;
+        with_submission_id(submission)
+    }
+
+    /// Adds the uploaded message `blob_id` to the store via Email/import.
+    /// Returns the new email's `{id, blobId, threadId, size}`.
+    pub async fn import_email(
+        &self,
+        blob_id: &str,
+        mailbox_ids: &[String],
+        keywords: &[String],
+        received_at: Option<jiff::Timestamp>,
+    ) -> Result<Value> {
+        let set = |names: &[String]| -> serde_json::Map<String, Value> {
+            names.iter().map(|name| (name.clone(), json!(true))).collect()
+        };
+        let mut email = json!({
+            "blobId": blob_id,
+            "mailboxIds": set(mailbox_ids),
+            "keywords": set(keywords)
+        });
+        if let Some(at) = received_at {
+            email["receivedAt"] = json!(at.to_string());
+        }
+        let result = self
+            .call(
+                "Email/import",
+                json!({ "accountId": self.account_id, "emails": { "import": email } }),
+            )
+            .await?;
+        if let Some(err) = result["notCreated"].get("import") {
+            bail!("server rejected the message: {}", set_error_text(err));
+        }
+        result["created"]
+            .get("import")
+            .cloned()
+            .context("Email/import returned no email")
+    }
+
+    /// The most recent email submissions, newest first.
+    pub async fn recent_submissions(&self, limit: u32) -> Result<Vec<Value>> {
+        let results = self
+            .call_multi(vec![
+                (
+                    "EmailSubmission/query",
+                    json!({
+                        "accountId": self.account_id,
+                        "sort": [{"property": "sentAt", "isAscending": false}],
+                        "limit": limit
+                    }),
+                    "q",
+                ),
+                (
+                    "EmailSubmission/get",
+                    json!({
+                        "accountId": self.account_id,
+                        "#ids": { "resultOf": "q", "name": "EmailSubmission/query", "path": "/ids" },
+                        "properties": ["id", "emailId", "sendAt", "undoStatus", "envelope"]
+                    }),
+                    "g",
+                ),
+            ])
+            .await?;
+
+        let list = results
+            .into_iter()
+            .nth(1)
+            .context("no EmailSubmission/get response")?["list"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        Ok(list)
+    }
+
+    /// Fetches one submission with its per-recipient `deliveryStatus`.
+    pub async fn get_submission(&self, id: &str) -> Result<Value> {
+        let result = self
+            .call(
+                "EmailSubmission/get",
+                json!({
+                    "accountId": self.account_id,
+                    "ids": [id],
+                    "properties": ["id", "emailId", "sendAt", "undoStatus", "deliveryStatus"]
+                }),
+            )
+            .await?;
+        result["list"]
+            .as_array()
+            .and_then(|list| list.first())
+            .cloned()
+            .with_context(|| format!("submission {id} not found"))
+    }
+
+    /// Cancels a submission that is still pending (within the server's
+    /// undo window or held for later delivery) and returns its message to
+    /// Drafts.
+    pub async fn cancel_submission(&self, id: &str) -> Result<()> {
+        let mut args = json!({
+            "accountId": self.account_id,
+            "update": { id: { "undoStatus": "canceled" } }
+        });
+        let drafts = self.mailbox_id_by_role("drafts").await?;
+        let sent = self.mailbox_id_by_role("sent").await?;
+        if let (Some(drafts), Some(sent)) = (drafts, sent) {
+            let mut patch = serde_json::Map::new();
+            patch.insert(format!("mailboxIds/{sent}"), Value::Null);
+            patch.insert(format!("mailboxIds/{drafts}"), json!(true));
+            patch.insert("keywords/$draft".into(), json!(true));
+            args["onSuccessUpdateEmail"] = json!({ id: patch });
+        }
+
+        let result = self.call("EmailSubmission/set", args).await?;
+        if let Some(err) = result["notUpdated"].get(id) {
+            if err["type"] == "cannotUnsend" {
+                bail!("submission {id} can no longer be canceled; the message has gone out");
+            }
+            bail!("server rejected canceling submission {id}: {}", set_error_text(err));
+        }
+        Ok(())
+    }
+
+    /// The account's vacation (out-of-office) auto-reply settings.
+    pub async fn get_vacation(&self) -> Result<Value> {
+        self.require(VACATION_CAPABILITY, "vacation responses")?;
+        let result = self
+            .call(
+                "VacationResponse/get",
+                json!({ "accountId": self.account_id, "ids": ["singleton"] }),
+            )
+            .await?;
+        result["list"]
+            .as_array()
+            .and_then(|list| list.first())
+            .cloned()
+            .context("server returned no vacation response")
+    }
+
+    /// Applies a VacationResponse/set `patch` to the account's singleton.
+    pub async fn set_vacation(&self, patch: Value) -> Result<()> {
+        self.require(VACATION_CAPABILITY, "vacation responses")?;
+        let result = self
+            .call(
+                "VacationResponse/set",
+                json!({ "accountId": self.account_id, "update": { "singleton": patch } }),
+            )
+            .await?;
+        if let Some(err) = result["notUpdated"].get("singleton") {
+            bail!("server rejected the vacation response: {}", set_error_text(err));
+        }
+        Ok(())
+    }
+
+    /// Fails unless the session advertised `capability`.
+    fn require(&self, capability: &str, feature: &str) -> Result<()> {
+        if !self.using.iter().any(|c| c == capability) {
+            bail!("server does not support {feature}");
+        }
+        Ok(())
+    }
+
+    pub async fn get_sieve_scripts(&self) -> Result<Value> {
+        self.require(SIEVE_CAPABILITY, "JMAP for Sieve")?;
+        self.call(
+            "SieveScript/get",
+            json!({
+                "accountId": self.account_id,
+                "properties": ["id", "name", "blobId", "isActive"]
+            }),
+        )
+        .await
+    }
+
+    /// Resolves a Sieve script given either its ID or its name.
+    pub async fn resolve_sieve_script(&self, script: &str) -> Result<Value> {
+        let scripts = self.get_sieve_scripts().await?;
+        let list = scripts["list"].as_array().context("SieveScript/get returned no list")?;
+        list.iter()
+            .find(|s| s["id"].as_str() == Some(script))
+            .or_else(|| list.iter().find(|s| s["name"].as_str() == Some(script)))
+            .cloned()
+            .with_context(|| format!("no Sieve script named or with id {script:?}"))
+    }
+
+    /// The source of a script returned by [`Self::get_sieve_scripts`].
+    pub async fn sieve_script_content(&self, script: &Value) -> Result<String> {
+        let blob_id = script["blobId"].as_str().context("Sieve script has no blobId")?;
+        let raw = self.download_blob(blob_id, "script.sieve", "application/sieve").await?;
+        String::from_utf8(raw).context("Sieve script is not UTF-8")
+    }
+
+    /// Stores `content` as the script `name`, replacing a script of that
+    /// name if there is one, and optionally makes it the active script.
+    /// Returns the script's ID. Scripts that don't compile are rejected by
+    /// the server with its error message.
+    pub async fn put_sieve_script(
+        &self,
+        name: &str,
+        content: &str,
+        activate: bool,
+    ) -> Result<String> {
+        let scripts = self.get_sieve_scripts().await?;
+        let existing = scripts["list"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find(|s| s["name"].as_str() == Some(name))
+            .and_then(|s| s["id"].as_str())
+            .map(String::from);
+
+        let blob = self.upload_blob(content.as_bytes().to_vec(), "application/sieve").await?;
+        let blob_id = blob["blobId"].as_str().context("upload returned no blobId")?;
+
+        let mut args = json!({ "accountId": self.account_id });
+        match &existing {
+            Some(id) => args["update"] = json!({ id: { "blobId": blob_id } }),
+            None => args["create"] = json!({ "script": { "name": name, "blobId": blob_id } }),
+        }
+        if activate {
+            args["onSuccessActivateScript"] = json!(existing.as_deref().unwrap_or("#script"));
+        }
+        let result = self.call("SieveScript/set", args).await?;
+        match existing {
+            Some(id) => {
+                if let Some(err) = result["notUpdated"].get(&id) {
+                    bail!("server rejected Sieve script {name:?}: {}", set_error_text(err));
+                }
+                Ok(id)
+            }
+            None => created_id(&result, "script"),
+        }
+    }
+
+    /// Checks that `content` compiles with SieveScript/validate, without
+    /// storing it as a script. Returns the server's error, if any.
+    pub async fn validate_sieve(&self, content: &str) -> Result<Option<String>> {
+        self.require(SIEVE_CAPABILITY, "JMAP for Sieve")?;
+        let blob = self.upload_blob(content.as_bytes().to_vec(), "application/sieve").await?;
+        let blob_id = blob["blobId"].as_str().context("upload returned no blobId")?;
+        let result = self
+            .call(
+                "SieveScript/validate",
+                json!({ "accountId": self.account_id, "blobId": blob_id }),
+            )
+            .await?;
+        Ok(match &result["error"] {
+            Value::Null => None,
+            err => Some(set_error_text(err)),
+        })
+    }
+
+    /// Makes script `id` the active one, deactivating any other.
+    pub async fn activate_sieve_script(&self, id: &str) -> Result<()> {
+        self.require(SIEVE_CAPABILITY, "JMAP for Sieve")?;
+        self.call(
+            "SieveScript/set",
+            json!({ "accountId": self.account_id, "onSuccessActivateScript": id }),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Deactivates the active script, leaving no server-side filtering.
+    pub async fn deactivate_sieve_scripts(&self) -> Result<()> {
+        self.require(SIEVE_CAPABILITY, "JMAP for Sieve")?;
+        self.call(
+            "SieveScript/set",
+            json!({ "accountId": self.account_id, "onSuccessDeactivateScript": true }),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Adds senders to the allowlist (or, with `deny`, the denylist) kept in
+    /// a managed block of the active Sieve script, creating and activating a
+    /// script if none is active. Returns the resulting lists.
+    pub async fn update_sender_lists(&self, entries: &[String], deny: bool) -> Result<Value> {
+        self.require(SIEVE_CAPABILITY, "JMAP for Sieve")?;
+
+        let scripts = self.get_sieve_scripts().await?;
+        let active = scripts["list"]
+            .as_array()
+            .and_then(|list| list.iter().find(|s| s["isActive"].as_bool() == Some(true)))
+            .cloned();
+
+        let current = match &active {
+            Some(script) => {
+                let blob_id = script["blobId"].as_str().context("active script has no blobId")?;
+                let raw = self.download_blob(blob_id, "script.sieve", "application/sieve").await?;
+                String::from_utf8(raw).context("active Sieve script is not UTF-8")?
+            }
+            None => String::new(),
+        };
+
+        let mut lists = SenderLists::parse(&current);
+        if deny {
+            lists.deny(entries);
+        } else {
+            lists.allow(entries);
+        }
+
+        let junk = self.find_mailbox_name_by_role("junk").await?.unwrap_or_else(|| "Junk".into());
+        let updated = sieve::apply(&current, &lists, &junk);
+        let blob = self.upload_blob(updated.into_bytes(), "application/sieve").await?;
+        let blob_id = blob["blobId"].as_str().context("upload returned no blobId")?;
+
+        let args = match &active {
+            Some(script) => {
+                let id = script["id"].as_str().context("active script has no id")?;
+                json!({
+                    "accountId": self.account_id,
+                    "update": { id: { "blobId": blob_id } }
+                })
+            }
+            None => json!({
+                "accountId": self.account_id,
+                "create": { "managed": { "name": "mcp-managed", "blobId": blob_id } },
+                "onSuccessActivateScript": "#managed"
+            }),
+        };
+        let result = self.call("SieveScript/set", args).await?;
+        if let Some(err) = result["notCreated"]["managed"].as_object() {
+            bail!("failed to create Sieve script: {}", Value::Object(err.clone()));
+        }
+        if let Some(errors) = result["notUpdated"].as_object()
+            && let Some(err) = errors.values().next()
+        {
+            bail!("failed to update Sieve script: {err}");
+        }
+
+        Ok(json!({ "allow": lists.allow, "deny": lists.deny }))
+    }
+
+    pub async fn find_mailbox_by_role(&self, role: &str) -> Result<Option<Value>> {
+        let result = self.get_mailboxes().await?;
+        Ok(result["list"]
+            .as_array()
+            .and_then(|list| list.iter().find(|m| m["role"].as_str() == Some(role)))
+            .cloned())
+    }
+
+    pub async fn find_mailbox_name_by_role(&self, role: &str) -> Result<Option<String>> {
+        let mailbox = self.find_mailbox_by_role(role).await?;
+        Ok(mailbox.and_then(|m| m["name"].as_str().map(|s| s.to_string())))
+    }
+
+    /// Summaries of the most recent messages in this account's Inbox.
+    pub async fn inbox_summaries(&self, limit: u32) -> Result<Vec<Value>> {
+        let inbox = self
+            .find_mailbox_by_role("inbox")
+            .await?
+            .context("no inbox mailbox found")?;
+        let inbox_id = inbox["id"].as_str().context("inbox has no id")?;
+        self.search_summaries(json!({"inMailbox": inbox_id}), limit).await
+    }
+
+    /// Registers a push subscription and returns it as created: its ID and
+    /// the fields the server set, such as `expires`.
+    pub async fn create_push_subscription(
+        &self,
+        device_client_id: &str,
+        url: &str,
+        types: Option<&[String]>,
+        expires: Option<&str>,
+    ) -> Result<Value> {
+        let mut subscription = json!({
+            "deviceClientId": device_client_id,
+            "url": url,
+        });
+        if let Some(types) = types {
+            subscription["types"] = json!(types);
+        }
+        if let Some(expires) = expires {
+            subscription["expires"] = json!(expires);
+        }
+
+        let result = self
+            .call("PushSubscription/set", json!({ "create": { "push": subscription } }))
+            .await?;
+        created_id(&result, "push")?;
+        Ok(result["created"]["push"].clone())
+    }
+
+    /// Activates a push subscription with the code the server sent to its
+    /// URL.
+    pub async fn verify_push_subscription(&self, id: &str, verification_code: &str) -> Result<()> {
+        let result = self
+            .call(
+                "PushSubscription/set",
+                json!({ "update": { id: { "verificationCode": verification_code } } }),
+            )
+            .await?;
+        if let Some(err) = result["notUpdated"].get(id) {
+            bail!("server rejected verification of push subscription {id}: {}", set_error_text(err));
+        }
+        Ok(())
+    }
+
+    pub async fn delete_push_subscription(&self, id: &str) -> Result<()> {
+        let result = self.call("PushSubscription/set", json!({ "destroy": [id] })).await?;
+        if let Some(err) = result["notDestroyed"].get(id) {
+            bail!("server rejected deletion of push subscription {id}: {}", set_error_text(err));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JmapResponse {
     method_responses: Vec<Vec<Value>>,
 }
+
+impl JmapResponse {
+    /// Each call's own response, keyed by call id. A call may add implicit
+    /// responses under its id after its own (EmailSubmission/set's
+    /// `onSuccessUpdateEmail` adds an Email/set); those are dropped.
+    fn by_call_id(self) -> HashMap<String, Vec<Value>> {
+        let mut responses = HashMap::new();
+        for r in self.method_responses {
+            if let Some(id) = r.get(2).and_then(Value::as_str) {
+                responses.entry(id.to_string()).or_insert(r);
+            }
+        }
+        responses
+    }
+}
+
+/// The server ID of object `key` created by a /set call, or its SetError.
+fn created_id(result: &Value, key: &str) -> Result<String> {
+    if let Some(err) = result["notCreated"].get(key) {
+        bail!("server rejected {key}: {}", set_error_text(err));
+    }
+    result["created"][key]["id"]
+        .as_str()
+        .map(String::from)
+        .with_context(|| format!("no id returned for {key}"))
+}
+
+/// Sets `truncated: true` on each email in an Email/get response whose
+/// body values were cut at `maxBodyValueBytes`, so callers can tell that
+/// content is missing and fetch again with a higher limit.
+fn mark_truncated(result: &mut Value) {
+    for email in result["list"].as_array_mut().into_iter().flatten() {
+        let truncated = email["bodyValues"]
+            .as_object()
+            .is_some_and(|values| values.values().any(|v| v["isTruncated"] == json!(true)));
+        if truncated {
+            email["truncated"] = json!(true);
+        }
+    }
+}
+
+/// Email properties fetched when reading messages in full.
+fn full_properties() -> Vec<&'static str> {
+    let mut properties = vec![
+        "id", "threadId", "mailboxIds", "from", "to", "cc", "bcc",
+        "subject", "receivedAt", "sentAt", "size", "keywords",
+        "preview", "textBody", "htmlBody", "bodyValues", "attachments",
+    ];
+    properties.extend_from_slice(headers::PROPERTIES);
+    properties
+}
+
+/// An attachment from Email/get reduced to what Email/set create accepts
+/// to attach the same blob to a new message.
+pub fn attachment_ref(part: &Value) -> Value {
+    let mut attachment = json!({
+        "blobId": part["blobId"],
+        "type": part["type"],
+        "disposition": part["disposition"].as_str().unwrap_or("attachment")
+    });
+    for key in ["name", "charset", "cid"] {
+        if !part[key].is_null() {
+            attachment[key] = part[key].clone();
+        }
+    }
+    attachment
+}
+
+/// The strings in a JSON array (e.g. `messageId`); empty for null.
+pub fn strings(value: &Value) -> Vec<String> {
+    value
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|v| v.as_str().map(String::from))
+        .collect()
+}
+
+/// The string field `key` of each object in a JSON array.
+fn strings_at(list: &Value, key: &str) -> Vec<String> {
+    list.as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|v| v[key].as_str().map(String::from))
+        .collect()
+}
+
+/// Surfaces the ID of the submission created as `send` at the top of an
+/// EmailSubmission/set response, for canceling it later. Fails when the
+/// server rejected the submission, so no send is reported that didn't
+/// happen.
+fn with_submission_id(mut result: Value) -> Result<Value> {
+    result["submissionId"] = json!(created_id(&result, "send")?);
+    Ok(result)
+}
+
+/// Renders a SetError as "type: description", e.g. "invalidProperties:
+/// name already exists".
+fn set_error_text(err: &Value) -> String {
+    let kind = err["type"].as_str().unwrap_or("error");
+    match err["description"].as_str() {
+        Some(description) => format!("{kind}: {description}"),
+        None => kind.to_string(),
+    }
+}
+
+/// Splits a /set response into succeeded IDs (`ok_key`, either an array or
+/// an object keyed by ID) and per-ID failures (`err_key`).
+fn collect_set_outcome(
+    result: &Value,
+    ok_key: &str,
+    err_key: &str,
+    ok: &mut Vec<String>,
+    failed: &mut Vec<Value>,
+) {
+    match &result[ok_key] {
+        Value::Array(ids) => ok.extend(ids.iter().filter_map(|id| id.as_str().map(String::from))),
+        Value::Object(map) => ok.extend(map.keys().cloned()),
+        _ => {}
+    }
+    if let Some(errors) = result[err_key].as_object() {
+        for (id, err) in errors {
+            failed.push(json!({ "id": id, "error": err }));
+        }
+    }
+}
+
+/// Percent-encodes a value for substitution into a URL template.
+fn url_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b'~') {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{b:02X}"));
+        }
+    }
+    out
+}
+
+/// Human-readable byte count, e.g. "25.0 MB".
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["bytes", "KB", "MB", "GB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1000.0 && unit < UNITS.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} bytes")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+/// Connection failures, timeouts, throttling and server errors are worth
+/// another attempt; other rejections are not.
+fn is_transient(error: &anyhow::Error) -> bool {
+    let Some(e) = error.downcast_ref::<reqwest::Error>() else {
+        return false;
+    };
+    match e.status() {
+        Some(status) => status.is_server_error() || status.as_u16() == 429,
+        None => e.is_connect() || e.is_timeout() || e.is_request() || e.is_body(),
+    }
+}
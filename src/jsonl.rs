@@ -0,0 +1,134 @@
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::index::sender_text;
+
+/// Output shape for list and search tools.
+#[derive(Debug, Default, Clone, Copy, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// The usual pretty-printed JSON document.
+    #[default]
+    Json,
+    /// One compact JSON object per line with a fixed set of keys.
+    Jsonl,
+    /// Compact markdown: a header block and body text per full email, or
+    /// one bullet per record for listings.
+    Markdown,
+    /// One plain line per record: id, date, sender, subject and preview
+    /// for emails, separated by ` | `.
+    Summary,
+}
+
+/// Schema description of `format` on tools that list emails.
+pub const EMAIL_LIST_FORMATS: &str = "\"json\" (default), \"jsonl\" for one object per line \
+    with fixed keys, for piping into scripts, \"markdown\" for a compact bullet list, or \
+    \"summary\" for one plain line per result (id, date, from, subject, preview)";
+
+/// Schema description of `format` on `get_mailboxes`.
+pub const MAILBOX_LIST_FORMATS: &str = "\"json\" (default), \"jsonl\" for one object per line \
+    with fixed keys, for piping into scripts, \"markdown\" for a compact bullet list, or \
+    \"summary\" for one plain line per mailbox (id, name, role, total and unread counts)";
+
+/// Joins records into JSON Lines text.
+pub fn render(records: &[Value]) -> String {
+    records
+        .iter()
+        .map(Value::to_string)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Records as summary lines: the identifying and descriptive fields first
+/// (id, date, sender, subject, preview), then counts, then `true` flags by
+/// name, joined by ` | `. Secondary IDs and empty fields are left out.
+pub fn summary(records: &[Value]) -> String {
+    const LEAD: [&str; 8] = ["id", "account", "date", "from", "subject", "preview", "name", "role"];
+    records
+        .iter()
+        .map(|record| {
+            let Some(fields) = record.as_object() else {
+                return record.to_string();
+            };
+            let dated = fields.get("date").is_some_and(|d| !d.is_null());
+            let skip = |k: &str| {
+                LEAD.contains(&k) || k.ends_with("_id") || (dated && k == "received_at")
+            };
+            let rest = fields.keys().filter(|k| !skip(k));
+            let mut parts = Vec::new();
+            for key in LEAD.iter().copied().chain(rest.map(String::as_str)) {
+                match fields.get(key) {
+                    Some(Value::String(s)) if !s.trim().is_empty() => {
+                        parts.push(s.split_whitespace().collect::<Vec<_>>().join(" "));
+                    }
+                    Some(Value::Number(n)) => parts.push(format!("{key}: {n}")),
+                    Some(Value::Bool(true)) => parts.push(key.to_string()),
+                    _ => {}
+                }
+            }
+            parts.join(" | ")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Email records from a tool result: a merged list, a `results`/`list`
+/// array, or a bare Email/query `ids` list. Every record carries the same
+/// keys; fields the source didn't include are null.
+pub fn emails(result: &Value) -> Vec<Value> {
+    let account_id = result["accountId"].as_str();
+    let items = match result {
+        Value::Array(items) => items.clone(),
+        _ if result["results"].is_array() => result["results"].as_array().cloned().unwrap_or_default(),
+        _ if result["list"].is_array() => result["list"].as_array().cloned().unwrap_or_default(),
+        _ => result["ids"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|id| json!({ "id": id }))
+            .collect(),
+    };
+
+    items
+        .iter()
+        .map(|e| {
+            let from = match &e["from"] {
+                Value::String(s) => Some(s.clone()),
+                Value::Array(_) => Some(sender_text(&e["from"])),
+                _ => None,
+            };
+            json!({
+                "account_id": e["account_id"].as_str().or(account_id),
+                "account": e["account"],
+                "id": e["id"],
+                "thread_id": e["threadId"],
+                "received_at": e["receivedAt"],
+                "date": e["date"],
+                "from": from,
+                "subject": e["subject"],
+                "preview": e["preview"],
+                "unread": e["keywords"].as_object().map(|k| !k.contains_key("$seen")),
+            })
+        })
+        .collect()
+}
+
+/// Mailbox records from a Mailbox/get result.
+pub fn mailboxes(result: &Value) -> Vec<Value> {
+    result["list"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|m| {
+            json!({
+                "id": m["id"],
+                "name": m["name"],
+                "parent_id": m["parentId"],
+                "role": m["role"],
+                "total": m["totalEmails"],
+                "unread": m["unreadEmails"],
+            })
+        })
+        .collect()
+}
@@ -1,21 +1,76 @@
+mod confirm;
+mod config;
+mod crypto;
+mod dates;
+mod debug;
+mod dsn;
+mod export;
+mod headers;
+mod html;
+mod index;
 mod jmap;
+mod jsonl;
+mod markdown;
+mod mcplog;
+mod mime;
+mod progress;
+mod prompts;
+mod push;
+mod reply;
+mod roots;
 mod server;
+mod sieve;
+mod spool;
+mod structured;
+mod suppression;
+mod template;
+mod tnef;
+mod watch;
+mod wirelog;
 
-use anyhow::{Context, Result};
+use anyhow::Result;
 use rmcp::{ServiceExt, transport::stdio};
+use std::sync::Arc;
 
+use config::{Credentials, Settings};
+use index::LocalIndex;
 use jmap::JmapClient;
 use server::StalwartServer;
+use suppression::SuppressionList;
+use wirelog::WireLog;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let session_url =
-        std::env::var("JMAP_SESSION_URL").context("JMAP_SESSION_URL is required")?;
-    let username = std::env::var("JMAP_USERNAME").context("JMAP_USERNAME is required")?;
-    let password = std::env::var("JMAP_PASSWORD").context("JMAP_PASSWORD is required")?;
+    let creds = Credentials::from_env()?;
+    let settings = Settings::from_env()?;
+
+    debug::init(creds.secrets());
+    tracing::debug!(session_url = %creds.session_url, username = %creds.username, "starting");
+
+    let wire_log = WireLog::from_env()?.map(Arc::new);
+
+    let client =
+        JmapClient::connect(&creds.session_url, &creds.username, &creds.password, wire_log).await?;
+    let index = LocalIndex::from_env()?;
+    let suppression = SuppressionList::from_env()?;
+    let server = StalwartServer::new(client, index, suppression, settings);
+
+    // SIGHUP re-reads credentials, e.g. after an app password rotation.
+    #[cfg(unix)]
+    {
+        let server = server.clone();
+        let mut hangup =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+        tokio::spawn(async move {
+            while hangup.recv().await.is_some() {
+                match server.reconnect().await {
+                    Ok(account) => tracing::info!(%account, "reconnected on SIGHUP"),
+                    Err(e) => tracing::warn!("reconnect on SIGHUP failed: {e:#}"),
+                }
+            }
+        });
+    }
 
-    let client = JmapClient::connect(&session_url, &username, &password).await?;
-    let server = StalwartServer::new(client);
     let service = server.serve(stdio()).await?;
     service.waiting().await?;
     Ok(())
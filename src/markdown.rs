@@ -0,0 +1,81 @@
+use jiff::tz::TimeZone;
+use serde_json::Value;
+
+use crate::dates;
+use crate::index::sender_text;
+use crate::reply::text_body;
+
+/// Emails from an Email/get-style result as compact markdown: a heading
+/// per message, a short header block, then the plain-text body. Far
+/// cheaper to read than the JMAP JSON it replaces.
+pub fn emails(result: &Value, tz: &TimeZone) -> String {
+    let list = match result {
+        Value::Array(items) => items.as_slice(),
+        _ => result["list"].as_array().map(Vec::as_slice).unwrap_or_default(),
+    };
+    list.iter()
+        .map(|email| email_block(email, tz))
+        .collect::<Vec<_>>()
+        .join("\n---\n\n")
+}
+
+fn email_block(email: &Value, tz: &TimeZone) -> String {
+    let subject = email["subject"].as_str().filter(|s| !s.trim().is_empty());
+    let mut out = format!("## {}\n\n", subject.unwrap_or("(no subject)"));
+
+    let mut field = |label: &str, value: String| {
+        if !value.is_empty() {
+            out.push_str(&format!("**{label}:** {value}  \n"));
+        }
+    };
+    field("From", sender_text(&email["from"]));
+    field("To", sender_text(&email["to"]));
+    field("Cc", sender_text(&email["cc"]));
+    let date = email["sentAt"].as_str().or(email["receivedAt"].as_str());
+    field("Date", date.map(|d| dates::friendly(d, tz)).unwrap_or_default());
+    field("ID", email["id"].as_str().unwrap_or_default().to_string());
+    let attachments: Vec<String> = email["attachments"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|a| {
+            let name = a["name"].as_str().unwrap_or("unnamed");
+            let kind = a["type"].as_str().unwrap_or("application/octet-stream");
+            format!("{name} ({kind}, {} bytes)", a["size"].as_u64().unwrap_or_default())
+        })
+        .collect();
+    field("Attachments", attachments.join(", "));
+    if email["truncated"] == Value::Bool(true) {
+        field("Note", "body truncated; fetch again with a higher max_body_bytes".to_string());
+    }
+
+    let body = text_body(email);
+    if !body.trim().is_empty() {
+        out.push('\n');
+        out.push_str(body.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+/// Flat records (as produced for JSON Lines) as a markdown bullet list,
+/// leaving out empty fields.
+pub fn records(records: &[Value]) -> String {
+    records
+        .iter()
+        .map(|record| {
+            let fields: Vec<String> = record
+                .as_object()
+                .into_iter()
+                .flatten()
+                .filter(|(_, v)| !v.is_null() && v.as_str() != Some(""))
+                .map(|(k, v)| match v {
+                    Value::String(s) => format!("{k}: {s}"),
+                    other => format!("{k}: {other}"),
+                })
+                .collect();
+            format!("- {}", fields.join(" · "))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,120 @@
+use rmcp::model::{LoggingLevel, LoggingMessageNotificationParam};
+use rmcp::{Peer, RoleServer};
+use serde_json::{Map, Value, json};
+use std::fmt::Debug;
+use std::sync::RwLock;
+use tokio::sync::mpsc;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Metadata, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::debug::redact;
+
+/// Longest field value forwarded; JMAP request and response bodies are cut
+/// to this many characters.
+const MAX_FIELD_CHARS: usize = 2000;
+
+struct Sink {
+    level: LoggingLevel,
+    tx: mpsc::UnboundedSender<LoggingMessageNotificationParam>,
+}
+
+/// Where log events go once the client has called `logging/setLevel`.
+static SINK: RwLock<Option<Sink>> = RwLock::new(None);
+
+/// Forwards this server's tracing events at or above `level` to the client
+/// as `notifications/message`, replacing any earlier destination. Sending
+/// happens on a background task so logging never waits on the transport.
+pub fn connect(peer: Peer<RoleServer>, level: LoggingLevel) {
+    let (tx, mut rx) = mpsc::unbounded_channel::<LoggingMessageNotificationParam>();
+    tokio::spawn(async move {
+        while let Some(message) = rx.recv().await {
+            // No tracing here: a failure would be logged straight back to us.
+            if peer.notify_logging_message(message).await.is_err() {
+                break;
+            }
+        }
+    });
+    *SINK.write().unwrap() = Some(Sink { level, tx });
+}
+
+/// The tracing layer that feeds [`connect`]'s destination. Only events
+/// from this crate are forwarded, not those of rmcp or the HTTP client.
+pub fn layer<S>() -> impl Layer<S>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    McpLayer.with_filter(tracing_subscriber::filter::filter_fn(wanted))
+}
+
+fn wanted(metadata: &Metadata) -> bool {
+    let ours = metadata.target().starts_with(env!("CARGO_CRATE_NAME"));
+    ours && SINK
+        .read()
+        .unwrap()
+        .as_ref()
+        .is_some_and(|sink| rank(logging_level(metadata.level())) >= rank(sink.level))
+}
+
+fn logging_level(level: &Level) -> LoggingLevel {
+    match *level {
+        Level::ERROR => LoggingLevel::Error,
+        Level::WARN => LoggingLevel::Warning,
+        Level::INFO => LoggingLevel::Info,
+        _ => LoggingLevel::Debug,
+    }
+}
+
+fn rank(level: LoggingLevel) -> u8 {
+    match level {
+        LoggingLevel::Debug => 0,
+        LoggingLevel::Info => 1,
+        LoggingLevel::Notice => 2,
+        LoggingLevel::Warning => 3,
+        LoggingLevel::Error => 4,
+        LoggingLevel::Critical => 5,
+        LoggingLevel::Alert => 6,
+        LoggingLevel::Emergency => 7,
+    }
+}
+
+struct McpLayer;
+
+impl<S: Subscriber> Layer<S> for McpLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let mut fields = Fields(Map::new());
+        event.record(&mut fields);
+        let logger = metadata
+            .target()
+            .strip_prefix(concat!(env!("CARGO_CRATE_NAME"), "::"))
+            .unwrap_or(metadata.target());
+        let message = LoggingMessageNotificationParam {
+            level: logging_level(metadata.level()),
+            logger: Some(logger.to_string()),
+            data: json!(fields.0),
+        };
+        if let Some(sink) = SINK.read().unwrap().as_ref() {
+            let _ = sink.tx.send(message);
+        }
+    }
+}
+
+/// Collects an event's fields, including its message, as redacted strings.
+struct Fields(Map<String, Value>);
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.record_str(field, &format!("{value:?}"));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        let mut value = redact(value);
+        if let Some((cut, _)) = value.char_indices().nth(MAX_FIELD_CHARS) {
+            value.truncate(cut);
+            value.push('…');
+        }
+        self.0.insert(field.name().to_string(), Value::String(value));
+    }
+}
@@ -0,0 +1,496 @@
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD;
+use serde_json::{Value, json};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+/// A plain-text `text/plain` MIME entity (its own headers plus a base64
+/// body), ready to be signed or encrypted as a unit.
+pub fn text_entity(body: &str) -> String {
+    format!(
+        "Content-Type: text/plain; charset=utf-8\r\n\
+         Content-Transfer-Encoding: base64\r\n\
+         \r\n\
+         {}",
+        base64_lines(body.as_bytes())
+    )
+}
+
+/// A multipart/alternative entity carrying `text` and its `html`
+/// rendering, for clients to pick from.
+pub fn alternative_entity(text: &str, html: &str) -> String {
+    let boundary = format!(
+        "alt-{:x}-{}",
+        jiff::Timestamp::now().as_nanosecond(),
+        SEQUENCE.fetch_add(1, Ordering::Relaxed)
+    );
+    let html_part = format!(
+        "Content-Type: text/html; charset=utf-8\r\n\
+         Content-Transfer-Encoding: base64\r\n\
+         \r\n\
+         {}",
+        base64_lines(html.as_bytes())
+    );
+    format!(
+        "Content-Type: multipart/alternative; boundary=\"{boundary}\"\r\n\
+         \r\n\
+         --{boundary}\r\n\
+         {}\
+         --{boundary}\r\n\
+         {html_part}\
+         --{boundary}--\r\n",
+        text_entity(text)
+    )
+}
+
+/// Prepends the outer message headers to `entity`. Bcc recipients are
+/// deliberately left out; they only appear in the submission envelope.
+pub fn message(from: &str, to: &[String], cc: &[String], subject: &str, entity: &[u8]) -> Vec<u8> {
+    let mut headers = vec![
+        format!("From: {from}"),
+        format!("To: {}", to.join(", ")),
+    ];
+    if !cc.is_empty() {
+        headers.push(format!("Cc: {}", cc.join(", ")));
+    }
+    headers.push(format!("Subject: {}", encode_word(subject)));
+    headers.push(format!("Date: {}", date_now()));
+    headers.push(format!("Message-ID: {}", message_id(from)));
+
+    // openssl output already declares MIME-Version.
+    let has_version = entity
+        .split(|&b| b == b'\n')
+        .take_while(|line| !line.is_empty() && *line != b"\r")
+        .any(|line| line.to_ascii_lowercase().starts_with(b"mime-version:"));
+    if !has_version {
+        headers.push("MIME-Version: 1.0".to_string());
+    }
+
+    let mut out = headers.join("\r\n").into_bytes();
+    out.extend_from_slice(b"\r\n");
+    out.extend_from_slice(entity);
+    out
+}
+
+/// MIME type for a file name's extension, for attachments sent without
+/// an explicit one.
+pub fn guess_type(name: &str) -> &'static str {
+    let ext = name.rsplit_once('.').map(|(_, e)| e.to_ascii_lowercase()).unwrap_or_default();
+    match ext.as_str() {
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "json" => "application/json",
+        "doc" => "application/msword",
+        "docx" => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "xls" => "application/vnd.ms-excel",
+        "xlsx" => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "pptx" => "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+        "ics" => "text/calendar",
+        "csv" => "text/csv",
+        "txt" | "log" => "text/plain",
+        "html" | "htm" => "text/html",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "eml" => "message/rfc822",
+        _ => "application/octet-stream",
+    }
+}
+
+/// RFC 2047 encodes a header value when it isn't plain ASCII.
+pub fn encode_word(value: &str) -> String {
+    if value.is_ascii() {
+        value.to_string()
+    } else {
+        format!("=?UTF-8?B?{}?=", STANDARD.encode(value))
+    }
+}
+
+/// Multipart nesting deeper than this is listed as an attachment rather
+/// than walked.
+const MAX_DEPTH: usize = 16;
+
+/// Parses a raw message locally into its main headers, text and HTML
+/// bodies and a list of attachments, for content that must not be handed
+/// to the server (decrypted PGP plaintext).
+pub fn parse(raw: &[u8]) -> Value {
+    let (headers, body) = split_entity(raw);
+    let header = |name: &str| header_value(&headers, name).map(decode_words);
+    let mut parsed = Parsed::default();
+    walk(&headers, body, &mut parsed, 0);
+    json!({
+        "from": header("From"),
+        "to": header("To"),
+        "cc": header("Cc"),
+        "replyTo": header("Reply-To"),
+        "subject": header("Subject"),
+        "sentAt": header("Date"),
+        "messageId": header("Message-ID"),
+        "text": parsed.text,
+        "html": parsed.html,
+        "attachments": parsed.attachments
+    })
+}
+
+#[derive(Default)]
+struct Parsed {
+    text: Option<String>,
+    html: Option<String>,
+    attachments: Vec<Value>,
+}
+
+fn walk(headers: &[(String, String)], body: &[u8], out: &mut Parsed, depth: usize) {
+    let content_type = header_value(headers, "Content-Type").unwrap_or("text/plain");
+    let (mime_type, params) = parse_params(content_type);
+    let disposition = header_value(headers, "Content-Disposition").map(parse_params);
+
+    if let Some(boundary) = param(&params, "boundary")
+        && mime_type.starts_with("multipart/")
+        && depth < MAX_DEPTH
+    {
+        for part in split_multipart(body, boundary) {
+            let (headers, body) = split_entity(part);
+            walk(&headers, body, out, depth + 1);
+        }
+        return;
+    }
+
+    let data = decode_transfer(header_value(headers, "Content-Transfer-Encoding"), body);
+    let name = disposition
+        .as_ref()
+        .and_then(|(_, p)| param(p, "filename"))
+        .or_else(|| param(&params, "name"))
+        .map(decode_words);
+    let inline = name.is_none() && disposition.as_ref().is_none_or(|(d, _)| d != "attachment");
+    let slot = match mime_type.as_str() {
+        "text/plain" if inline => Some(&mut out.text),
+        "text/html" if inline => Some(&mut out.html),
+        _ => None,
+    };
+    match slot {
+        Some(slot) => {
+            let text = decode_charset(&data, param(&params, "charset"));
+            match slot {
+                Some(existing) => {
+                    existing.push_str("\n\n");
+                    existing.push_str(&text);
+                }
+                None => *slot = Some(text),
+            }
+        }
+        None => {
+            out.attachments.push(json!({ "name": name, "type": mime_type, "size": data.len() }));
+        }
+    }
+}
+
+/// Splits an entity at the first empty line into its unfolded headers and
+/// its body. An entity with no empty line is all headers.
+fn split_entity(raw: &[u8]) -> (Vec<(String, String)>, &[u8]) {
+    let mut end = raw.len();
+    let mut body = raw.len();
+    let mut start = 0;
+    while start < raw.len() {
+        let line_end = line_end(raw, start);
+        if matches!(&raw[start..line_end], b"\n" | b"\r\n") {
+            end = start;
+            body = line_end;
+            break;
+        }
+        start = line_end;
+    }
+
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for line in String::from_utf8_lossy(&raw[..end]).lines() {
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    (headers, &raw[body..])
+}
+
+/// Where the line starting at `start` ends, past its line break.
+fn line_end(data: &[u8], start: usize) -> usize {
+    data[start..].iter().position(|&b| b == b'\n').map_or(data.len(), |i| start + i + 1)
+}
+
+fn header_value<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+}
+
+/// Splits "type/subtype; name=value; ..." into the lowercased value and
+/// its parameters. RFC 2231 `name*=charset''percent-encoded` values are
+/// decoded; continuations (`name*0=`) are not.
+fn parse_params(value: &str) -> (String, Vec<(String, String)>) {
+    let mut fields = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in value.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                current.push(c);
+            }
+            ';' if !quoted => fields.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    fields.push(current);
+
+    let mut fields = fields.into_iter();
+    let kind = fields.next().unwrap_or_default().trim().to_ascii_lowercase();
+    let params = fields
+        .filter_map(|field| {
+            let (name, value) = field.split_once('=')?;
+            let name = name.trim().to_ascii_lowercase();
+            let value = value.trim().trim_matches('"');
+            match name.strip_suffix('*') {
+                Some(name) => {
+                    let encoded = value.splitn(3, '\'').nth(2).unwrap_or(value);
+                    let bytes = percent_decode(encoded);
+                    Some((name.to_string(), String::from_utf8_lossy(&bytes).into_owned()))
+                }
+                None => Some((name, value.to_string())),
+            }
+        })
+        .collect();
+    (kind, params)
+}
+
+fn param<'a>(params: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    params.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+}
+
+/// The parts of a multipart body, without their delimiter lines. Anything
+/// before the first delimiter or after the closing one is dropped.
+fn split_multipart<'a>(body: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let delimiter = format!("--{boundary}");
+    let mut parts = Vec::new();
+    let mut part_start: Option<usize> = None;
+    let mut start = 0;
+    while start < body.len() {
+        let line_end = line_end(body, start);
+        let line = body[start..line_end].trim_ascii_end();
+        if let Some(rest) = line.strip_prefix(delimiter.as_bytes())
+            && (rest.is_empty() || rest == b"--")
+        {
+            if let Some(from) = part_start {
+                // The line break before a delimiter belongs to the delimiter.
+                let mut end = start;
+                if body[..end].ends_with(b"\n") {
+                    end -= 1;
+                }
+                if body[..end].ends_with(b"\r") {
+                    end -= 1;
+                }
+                parts.push(&body[from..end.max(from)]);
+            }
+            if rest == b"--" {
+                return parts;
+            }
+            part_start = Some(line_end);
+        }
+        start = line_end;
+    }
+    if let Some(from) = part_start {
+        parts.push(&body[from..]);
+    }
+    parts
+}
+
+fn decode_transfer(encoding: Option<&str>, body: &[u8]) -> Vec<u8> {
+    match encoding.map(|e| e.trim().to_ascii_lowercase()).as_deref() {
+        Some("base64") => {
+            let clean: Vec<u8> =
+                body.iter().copied().filter(|b| !b.is_ascii_whitespace()).collect();
+            STANDARD.decode(&clean).unwrap_or_else(|_| body.to_vec())
+        }
+        Some("quoted-printable") => quoted_printable(body, false),
+        _ => body.to_vec(),
+    }
+}
+
+/// Decodes quoted-printable; `underscores` is the RFC 2047 "Q" variant,
+/// where `_` stands for a space.
+fn quoted_printable(data: &[u8], underscores: bool) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut i = 0;
+    while i < data.len() {
+        let rest = &data[i + 1..];
+        match data[i] {
+            b'=' if hex_pair(rest).is_some() => {
+                out.extend(hex_pair(rest));
+                i += 3;
+            }
+            // A soft line break.
+            b'=' if rest.starts_with(b"\r\n") => i += 3,
+            b'=' if rest.starts_with(b"\n") => i += 2,
+            b'_' if underscores => {
+                out.push(b' ');
+                i += 1;
+            }
+            b => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+fn hex_pair(data: &[u8]) -> Option<u8> {
+    let digits = data.get(..2)?;
+    if !digits.iter().all(u8::is_ascii_hexdigit) {
+        return None;
+    }
+    u8::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()
+}
+
+fn percent_decode(value: &str) -> Vec<u8> {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match (bytes[i], hex_pair(&bytes[i + 1..])) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (b, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+/// Text in `charset`. UTF-8 and ASCII are decoded as such, Latin-1 and
+/// Windows-1252 byte by byte; anything else is read as UTF-8, lossily.
+fn decode_charset(data: &[u8], charset: Option<&str>) -> String {
+    match charset.map(|c| c.to_ascii_lowercase()).as_deref() {
+        Some("iso-8859-1" | "latin1" | "windows-1252" | "cp1252") => {
+            data.iter().map(|&b| b as char).collect()
+        }
+        _ => String::from_utf8_lossy(data).into_owned(),
+    }
+}
+
+/// Decodes the RFC 2047 encoded words in a header value. Whitespace
+/// between two adjacent encoded words is dropped, as the RFC requires.
+fn decode_words(value: &str) -> String {
+    let mut out = String::new();
+    let mut rest = value;
+    let mut after_word = false;
+    while let Some(start) = rest.find("=?") {
+        let decoded = rest[start + 2..].split_once("?=").and_then(|(word, _)| {
+            let mut fields = word.splitn(3, '?');
+            let (charset, encoding, text) = (fields.next()?, fields.next()?, fields.next()?);
+            let bytes = match encoding.to_ascii_uppercase().as_str() {
+                "B" => STANDARD.decode(text).ok()?,
+                "Q" => quoted_printable(text.as_bytes(), true),
+                _ => return None,
+            };
+            Some((word.len(), decode_charset(&bytes, Some(charset))))
+        });
+        let Some((len, text)) = decoded else {
+            out.push_str(&rest[..start + 2]);
+            rest = &rest[start + 2..];
+            after_word = false;
+            continue;
+        };
+        let between = &rest[..start];
+        if !(after_word && between.trim().is_empty()) {
+            out.push_str(between);
+        }
+        out.push_str(&text);
+        rest = &rest[start + 2 + len + 2..];
+        after_word = true;
+    }
+    out.push_str(rest);
+    out
+}
+
+fn base64_lines(data: &[u8]) -> String {
+    let encoded = STANDARD.encode(data);
+    let mut out = String::with_capacity(encoded.len() + encoded.len() / 38);
+    for chunk in encoded.as_bytes().chunks(76) {
+        out.push_str(std::str::from_utf8(chunk).unwrap_or_default());
+        out.push_str("\r\n");
+    }
+    out
+}
+
+fn date_now() -> String {
+    jiff::fmt::rfc2822::to_string(&jiff::Zoned::now()).unwrap_or_default()
+}
+
+fn message_id(from: &str) -> String {
+    let domain = from.rsplit_once('@').map(|(_, d)| d).unwrap_or("localhost");
+    let now = jiff::Timestamp::now().as_nanosecond();
+    let seq = SEQUENCE.fetch_add(1, Ordering::Relaxed);
+    format!("<{now:x}.{:x}.{seq}@{domain}>", std::process::id())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_multipart_alternative_with_attachment() {
+        let raw = b"From: =?UTF-8?B?SsO8cmdlbg==?= <j@example.com>\r\n\
+Subject: =?ISO-8859-1?Q?Gr=FC=DFe?= =?UTF-8?Q?_aus_Bern?=\r\n\
+Content-Type: multipart/mixed; boundary=\"outer\"\r\n\
+\r\n\
+preamble\r\n\
+--outer\r\n\
+Content-Type: multipart/alternative; boundary=inner\r\n\
+\r\n\
+--inner\r\n\
+Content-Type: text/plain; charset=utf-8\r\n\
+Content-Transfer-Encoding: quoted-printable\r\n\
+\r\n\
+Caf=C3=A9 at =\r\nnoon\r\n\
+--inner\r\n\
+Content-Type: text/html\r\n\
+\r\n\
+<p>Cafe</p>\r\n\
+--inner--\r\n\
+--outer\r\n\
+Content-Type: application/pdf\r\n\
+Content-Disposition: attachment; filename*=utf-8''r%C3%A9sum%C3%A9.pdf\r\n\
+Content-Transfer-Encoding: base64\r\n\
+\r\n\
+aGVs\r\nbG8=\r\n\
+--outer--\r\n";
+        let parsed = parse(raw);
+        assert_eq!(parsed["from"], "Jürgen <j@example.com>");
+        assert_eq!(parsed["subject"], "Grüße aus Bern");
+        assert_eq!(parsed["text"], "Café at noon");
+        assert_eq!(parsed["html"], "<p>Cafe</p>");
+        assert_eq!(
+            parsed["attachments"],
+            json!([{ "name": "résumé.pdf", "type": "application/pdf", "size": 5 }])
+        );
+    }
+
+    #[test]
+    fn treats_a_bare_message_as_plain_text() {
+        let parsed = parse(b"Subject: hi\n\nline one\nline two\n");
+        assert_eq!(parsed["subject"], "hi");
+        assert_eq!(parsed["text"], "line one\nline two\n");
+        assert_eq!(parsed["attachments"], json!([]));
+    }
+
+    #[test]
+    fn keeps_undecodable_words_as_written() {
+        assert_eq!(decode_words("=?x?Z?abc?= plain =?"), "=?x?Z?abc?= plain =?");
+    }
+}
@@ -0,0 +1,71 @@
+use rmcp::model::{Meta, ProgressNotificationParam, ProgressToken};
+use rmcp::{Peer, RoleServer};
+use tokio::sync::mpsc;
+
+/// Sends MCP progress notifications for the current request, if the client
+/// asked for them with a progress token. Updates are forwarded in order by
+/// a background task, so reporting never blocks the work being measured.
+#[derive(Clone)]
+pub struct Progress {
+    tx: Option<mpsc::UnboundedSender<ProgressNotificationParam>>,
+    token: Option<ProgressToken>,
+    /// Work already done before this stage, and the whole job's size.
+    base: u64,
+    total: Option<u64>,
+}
+
+impl Progress {
+    pub fn new(peer: Peer<RoleServer>, meta: &Meta) -> Self {
+        let Some(token) = meta.get_progress_token() else {
+            return Self::none();
+        };
+        let (tx, mut rx) = mpsc::unbounded_channel::<ProgressNotificationParam>();
+        tokio::spawn(async move {
+            while let Some(update) = rx.recv().await {
+                if let Err(e) = peer.notify_progress(update).await {
+                    tracing::debug!("failed to send progress notification: {e}");
+                    break;
+                }
+            }
+        });
+        Self {
+            tx: Some(tx),
+            token: Some(token),
+            base: 0,
+            total: None,
+        }
+    }
+
+    /// A reporter that discards every update.
+    pub fn none() -> Self {
+        Self {
+            tx: None,
+            token: None,
+            base: 0,
+            total: None,
+        }
+    }
+
+    /// A reporter for one stage of a larger job, so progress keeps rising
+    /// across stages: updates are offset by `done` and measured against
+    /// the job's `total`.
+    pub fn stage(&self, done: u64, total: u64) -> Self {
+        Self {
+            tx: self.tx.clone(),
+            token: self.token.clone(),
+            base: self.base + done,
+            total: Some(total),
+        }
+    }
+
+    pub fn report(&self, progress: u64, total: Option<u64>, message: impl Into<String>) {
+        if let (Some(tx), Some(token)) = (&self.tx, &self.token) {
+            let _ = tx.send(ProgressNotificationParam {
+                progress_token: token.clone(),
+                progress: (self.base + progress) as f64,
+                total: self.total.or(total).map(|t| t as f64),
+                message: Some(message.into()),
+            });
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use rmcp::model::{JsonObject, Prompt, PromptArgument};
+
+/// The prompts offered to MCP clients' prompt pickers. Each one fetches
+/// the relevant mail and puts it into the prompt text, so the conversation
+/// starts with the messages already in context.
+pub fn list() -> Vec<Prompt> {
+    vec![
+        Prompt::new(
+            "summarize_inbox",
+            Some("Summarize the most recent emails in a mailbox, grouped by topic"),
+            Some(vec![mailbox_argument(), limit_argument(20)]),
+        ),
+        Prompt::new(
+            "draft_reply",
+            Some("Draft a reply to an email, with the original message included"),
+            Some(vec![
+                argument("email_id", "ID of the email to reply to", true),
+                argument(
+                    "instructions",
+                    "What the reply should say or how it should sound (optional)",
+                    false,
+                ),
+            ]),
+        ),
+        Prompt::new(
+            "triage_unread",
+            Some("Sort unread emails into what needs attention and what can be archived"),
+            Some(vec![mailbox_argument(), limit_argument(30)]),
+        ),
+    ]
+}
+
+fn argument(name: &str, description: &str, required: bool) -> PromptArgument {
+    PromptArgument {
+        name: name.into(),
+        title: None,
+        description: Some(description.into()),
+        required: Some(required),
+    }
+}
+
+fn mailbox_argument() -> PromptArgument {
+    argument("mailbox", "Mailbox name, role or ID (default the Inbox)", false)
+}
+
+fn limit_argument(default: u32) -> PromptArgument {
+    argument("limit", &format!("How many emails to include (default {default})"), false)
+}
+
+/// A prompt argument as text. Clients send strings, but numbers and
+/// booleans are accepted too.
+pub fn text(arguments: &JsonObject, name: &str) -> Option<String> {
+    match arguments.get(name)? {
+        serde_json::Value::String(s) => Some(s.trim().to_string()).filter(|s| !s.is_empty()),
+        serde_json::Value::Null => None,
+        other => Some(other.to_string()),
+    }
+}
+
+pub fn summarize_inbox(mailbox: &str, emails: &str) -> String {
+    format!(
+        "Summarize these recent emails from my {mailbox} mailbox. Group related messages by \
+         topic or conversation, say in a sentence or two what each group is about, and list \
+         anything that needs a reply or action from me first. Mention email IDs so I can ask \
+         for details.\n\n{}",
+        listing(emails)
+    )
+}
+
+pub fn triage_unread(mailbox: &str, emails: &str) -> String {
+    format!(
+        "Triage these unread emails from my {mailbox} mailbox. Sort them into: urgent, needs \
+         a reply, for information only, and can be archived or deleted. Give one line per \
+         email with its ID and the reason. Then suggest tool calls to tidy up (mark_read, \
+         flag_emails, archive_emails), but don't make them until I confirm.\n\n{}",
+        listing(emails)
+    )
+}
+
+pub fn draft_reply(email_id: &str, email: &str, instructions: Option<&str>) -> String {
+    let instructions = match instructions {
+        Some(i) => format!("Instructions for the reply: {i}\n\n"),
+        None => String::new(),
+    };
+    format!(
+        "Draft a reply to the email below. Match its language and tone, answer every question \
+         it asks, and keep it short. Write only the reply text; the original is quoted \
+         automatically. Show me the draft first; once I approve it, send it with reply_email \
+         (id {email_id}).\n\n{instructions}{email}"
+    )
+}
+
+/// System prompt for replies drafted through MCP sampling.
+pub const REPLY_SYSTEM_PROMPT: &str = "You write email replies on the user's behalf. Reply in \
+     the language and tone of the original, answer what it asks, and keep it short. Output \
+     only the reply body as plain text: no subject line, no quoted original, no commentary.";
+
+/// The sampling request for a reply to `email` (rendered as markdown).
+pub fn reply_request(email: &str, instructions: Option<&str>) -> String {
+    let instructions = match instructions {
+        Some(i) => format!("\n\nInstructions for the reply: {i}"),
+        None => String::new(),
+    };
+    format!("Write a reply to this email.{instructions}\n\n{email}")
+}
+
+fn listing(emails: &str) -> String {
+    if emails.trim().is_empty() {
+        return "(no matching emails)".to_string();
+    }
+    emails.lines().map(|line| format!("- {line}\n")).collect()
+}
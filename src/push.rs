@@ -0,0 +1,269 @@
+use anyhow::{Context, Result};
+use futures_util::StreamExt;
+use serde_json::Value;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
+
+use crate::jmap::JmapClient;
+
+/// How often the server is asked to ping an otherwise idle stream.
+const PING: Duration = Duration::from_secs(30);
+
+/// A stream that sends nothing, not even a ping, for this long is treated
+/// as dropped.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(120);
+
+/// A JMAP EventSource connection (RFC 8620 section 7.3) that wakes the
+/// mailbox change poller whenever the server reports a Mailbox or Email
+/// state change. Dropped connections are reopened with jittered backoff,
+/// resuming after the last event seen; while disconnected the poller falls
+/// back to checking on its own interval, so no change goes unnoticed.
+#[derive(Default)]
+pub struct Push {
+    connected: AtomicBool,
+    changed: Notify,
+}
+
+impl Push {
+    /// Whether a stream is currently open, so the poller can rely on it.
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    /// Waits for a state change, a reconnect or a disconnect; each is a cue
+    /// to check for changes. A wake-up sent while nobody waits is kept.
+    pub async fn changed(&self) {
+        self.changed.notified().await
+    }
+
+    /// Wakes the waiter in [`Self::changed`].
+    pub fn wake(&self) {
+        self.changed.notify_one();
+    }
+
+    /// Keeps a stream open until `stop` is cancelled, always through the
+    /// session's current client. Returns at once when the server offers no
+    /// EventSource.
+    pub async fn listen(&self, client: Arc<RwLock<Arc<JmapClient>>>, stop: CancellationToken) {
+        let mut last_event_id = None;
+        let mut attempt = 0;
+        loop {
+            let current = client.read().unwrap().clone();
+            let result = tokio::select! {
+                result = self.stream(&current, &mut last_event_id, &mut attempt) => result,
+                _ = stop.cancelled() => {
+                    self.connected.store(false, Ordering::Relaxed);
+                    return;
+                }
+            };
+            self.connected.store(false, Ordering::Relaxed);
+            self.wake();
+            match result {
+                Ok(false) => {
+                    tracing::debug!("server offers no EventSource, polling for mailbox changes");
+                    return;
+                }
+                Ok(true) => tracing::info!("EventSource stream closed by the server, reconnecting"),
+                Err(e) => tracing::warn!(attempt, "EventSource connection lost: {e:#}"),
+            }
+
+            attempt += 1;
+            tokio::select! {
+                _ = tokio::time::sleep(backoff(attempt)) => {}
+                _ = stop.cancelled() => return,
+            }
+        }
+    }
+
+    /// Reads one connection until it ends: Ok(true) when the server closed
+    /// it, Ok(false) when there is no EventSource to connect to.
+    async fn stream(
+        &self,
+        client: &JmapClient,
+        last_event_id: &mut Option<String>,
+        attempt: &mut u32,
+    ) -> Result<bool> {
+        let Some(resp) = client.open_event_source(last_event_id.as_deref(), PING).await? else {
+            return Ok(false);
+        };
+        self.connected.store(true, Ordering::Relaxed);
+        // Catch up on anything that changed while the stream was down, in
+        // case the server can't replay from the last event ID.
+        self.wake();
+
+        let mut parser = EventParser::resuming(last_event_id.clone());
+        let mut body = resp.bytes_stream();
+        loop {
+            let Some(chunk) = tokio::time::timeout(IDLE_TIMEOUT, body.next())
+                .await
+                .context("EventSource stream went silent")?
+            else {
+                return Ok(true);
+            };
+            let chunk = chunk.context("failed to read EventSource stream")?;
+            *attempt = 0;
+            for event in parser.feed(&chunk) {
+                if is_change(&event) {
+                    self.wake();
+                }
+            }
+            last_event_id.clone_from(&parser.last_event_id);
+        }
+    }
+}
+
+/// Delay before reconnect attempt `attempt` (counting from 1): doubling
+/// from [`MIN_BACKOFF`] up to [`MAX_BACKOFF`], then randomly cut by up to
+/// half so clients dropped together don't all reconnect together.
+fn backoff(attempt: u32) -> Duration {
+    let doublings = attempt.saturating_sub(1).min(16);
+    let ceiling = MIN_BACKOFF.saturating_mul(1 << doublings).min(MAX_BACKOFF);
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u32(attempt);
+    let random = (hasher.finish() % 1000) as f64 / 1000.0;
+    ceiling / 2 + ceiling.mul_f64(random / 2.0)
+}
+
+/// Whether `event` reports a Mailbox or Email state change in any account.
+fn is_change(event: &Event) -> bool {
+    if event.kind != "state" {
+        return false;
+    }
+    let Ok(data) = serde_json::from_str::<Value>(&event.data) else {
+        return false;
+    };
+    data["changed"]
+        .as_object()
+        .into_iter()
+        .flat_map(|accounts| accounts.values())
+        .any(|types| types.get("Mailbox").is_some() || types.get("Email").is_some())
+}
+
+/// One dispatched server-sent event.
+#[derive(Debug, PartialEq)]
+struct Event {
+    kind: String,
+    data: String,
+}
+
+/// Incremental `text/event-stream` parser, fed the body as it arrives.
+#[derive(Default)]
+struct EventParser {
+    buffer: Vec<u8>,
+    kind: String,
+    data: String,
+    /// The most recent `id` field, sent back as `Last-Event-ID`.
+    last_event_id: Option<String>,
+}
+
+impl EventParser {
+    fn resuming(last_event_id: Option<String>) -> Self {
+        Self {
+            last_event_id,
+            ..Self::default()
+        }
+    }
+
+    /// Adds `chunk` and returns the events it completes.
+    fn feed(&mut self, chunk: &[u8]) -> Vec<Event> {
+        self.buffer.extend_from_slice(chunk);
+        let mut events = Vec::new();
+        while let Some(end) = self.buffer.iter().position(|&b| b == b'\n') {
+            let raw: Vec<u8> = self.buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&raw[..end]);
+            let line = line.strip_suffix('\r').unwrap_or(&line);
+
+            if line.is_empty() {
+                events.extend(self.dispatch());
+                continue;
+            }
+            if line.starts_with(':') {
+                continue;
+            }
+            let (field, value) = match line.split_once(':') {
+                Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+                None => (line, ""),
+            };
+            match field {
+                "event" => self.kind = value.to_string(),
+                "data" => {
+                    self.data.push_str(value);
+                    self.data.push('\n');
+                }
+                "id" if !value.contains('\0') => self.last_event_id = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        events
+    }
+
+    /// Completes the pending event; one without data is dropped.
+    fn dispatch(&mut self) -> Option<Event> {
+        let kind = std::mem::take(&mut self.kind);
+        let mut data = std::mem::take(&mut self.data);
+        if data.is_empty() {
+            return None;
+        }
+        data.pop();
+        let kind = if kind.is_empty() { "message".to_string() } else { kind };
+        Some(Event { kind, data })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_events_split_across_chunks() {
+        let mut parser = EventParser::default();
+        assert!(parser.feed(b"event: state\r\nid: 4").is_empty());
+        let events = parser.feed(b"2\r\ndata: {\"a\":\ndata:1}\r\n\r\n: comment\n\ndata: x\n\n");
+        assert_eq!(
+            events,
+            [
+                Event { kind: "state".into(), data: "{\"a\":\n1}".into() },
+                Event { kind: "message".into(), data: "x".into() },
+            ]
+        );
+        assert_eq!(parser.last_event_id.as_deref(), Some("42"));
+    }
+
+    #[test]
+    fn keeps_the_last_event_id_when_resuming() {
+        let mut parser = EventParser::resuming(Some("7".into()));
+        parser.feed(b"event: ping\ndata: {}\n\n");
+        assert_eq!(parser.last_event_id.as_deref(), Some("7"));
+        parser.feed(b"id: 8\nevent: state\n\n");
+        assert_eq!(parser.last_event_id.as_deref(), Some("8"));
+    }
+
+    #[test]
+    fn recognizes_mail_state_changes() {
+        let state = |data: &str| Event { kind: "state".into(), data: data.into() };
+        assert!(is_change(&state(
+            r#"{"@type":"StateChange","changed":{"a1":{"Email":"s2"}}}"#
+        )));
+        assert!(!is_change(&state(
+            r#"{"@type":"StateChange","changed":{"a1":{"Identity":"s2"}}}"#
+        )));
+        assert!(!is_change(&Event { kind: "ping".into(), data: "{}".into() }));
+    }
+
+    #[test]
+    fn backs_off_with_jitter_up_to_the_cap() {
+        for attempt in 1..40 {
+            let ceiling = MIN_BACKOFF.saturating_mul(1 << (attempt - 1).min(16)).min(MAX_BACKOFF);
+            let delay = backoff(attempt);
+            assert!(delay >= ceiling / 2 && delay <= ceiling, "{attempt}: {delay:?}");
+        }
+    }
+}
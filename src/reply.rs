@@ -0,0 +1,283 @@
+use jiff::Timestamp;
+use jiff::tz::TimeZone;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use crate::index::sender_text;
+use crate::template;
+
+/// Reply prefixes in common mail clients' locales, matched
+/// case-insensitively so replies to "AW: ..." don't become "Re: AW: ...".
+const REPLY_PREFIXES: &[&str] = &[
+    "re", "aw", "sv", "vs", "antw", "ref", "réf", "rif", "odp", "ynt", "atb", "vá", "ha",
+    "απ", "σχετ", "отв", "回复", "回覆", "答复",
+];
+
+/// Forward prefixes, as above.
+const FORWARD_PREFIXES: &[&str] = &[
+    "fwd", "fw", "wg", "vs", "vb", "tr", "rv", "enc", "pd", "doorst", "ilt", "továbbítás",
+    "πρθ", "пересл", "转发", "轉寄",
+];
+
+/// Wording used when building replies and forwards, for non-English
+/// deployments.
+pub struct Phrases {
+    /// Subject prefix for replies (`REPLY_PREFIX`, default "Re:").
+    pub reply_prefix: String,
+    /// Subject prefix for forwards (`FORWARD_PREFIX`, default "Fwd:").
+    pub forward_prefix: String,
+    /// Line above a quoted original (`QUOTE_HEADER`, default
+    /// "On {{date}}, {{sender}} wrote:").
+    pub quote_header: String,
+}
+
+impl Phrases {
+    pub fn from_env() -> Self {
+        let var = |name: &str, default: &str| {
+            std::env::var(name)
+                .ok()
+                .filter(|v| !v.trim().is_empty())
+                .unwrap_or_else(|| default.to_string())
+        };
+        Self {
+            reply_prefix: var("REPLY_PREFIX", "Re:"),
+            forward_prefix: var("FORWARD_PREFIX", "Fwd:"),
+            quote_header: var("QUOTE_HEADER", "On {{date}}, {{sender}} wrote:"),
+        }
+    }
+
+    /// `subject` with the reply prefix, unless it already carries one in
+    /// any language.
+    pub fn reply_subject(&self, subject: &str) -> String {
+        with_prefix(subject, &self.reply_prefix, REPLY_PREFIXES)
+    }
+
+    /// `subject` with the forward prefix, unless it already carries one.
+    pub fn forward_subject(&self, subject: &str) -> String {
+        with_prefix(subject, &self.forward_prefix, FORWARD_PREFIXES)
+    }
+
+    /// The quote header for `original`, with its date rendered in `tz`.
+    pub fn quote_header(&self, original: &Value, tz: &TimeZone) -> String {
+        let variables = BTreeMap::from([
+            ("date".to_string(), sent_date(original, tz)),
+            ("sender".to_string(), sender_text(&original["from"])),
+        ]);
+        template::render(&self.quote_header, &variables)
+            .unwrap_or_else(|_| self.quote_header.clone())
+    }
+}
+
+/// When `email` was sent (or else received), spelled out in `tz`.
+fn sent_date(email: &Value, tz: &TimeZone) -> String {
+    email["sentAt"]
+        .as_str()
+        .or(email["receivedAt"].as_str())
+        .and_then(|d| d.parse::<Timestamp>().ok())
+        .map(|ts| ts.to_zoned(tz.clone()).strftime("%a, %-d %b %Y at %H:%M").to_string())
+        .unwrap_or_default()
+}
+
+fn with_prefix(subject: &str, prefix: &str, known: &[&str]) -> String {
+    let subject = subject.trim();
+    let head = subject
+        .split([':', '：'])
+        .next()
+        .filter(|head| head.len() < subject.len())
+        .unwrap_or_default();
+    // Some clients count replies, e.g. "Re[2]:".
+    let head = head.split('[').next().unwrap_or_default().trim().to_lowercase();
+    let configured = prefix.trim().trim_end_matches([':', '：']).trim().to_lowercase();
+    if !head.is_empty() && (head == configured || known.contains(&head.as_str())) {
+        return subject.to_string();
+    }
+    format!("{} {subject}", prefix.trim())
+}
+
+/// `text` quoted line by line below `header`, mail-client style.
+pub fn quote(header: &str, text: &str) -> String {
+    let mut out = format!("{header}\n");
+    for line in text.trim_end().lines() {
+        if line.is_empty() || line.starts_with('>') {
+            out.push('>');
+        } else {
+            out.push_str("> ");
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    out
+}
+
+/// The original as it appears below a forwarding note: a header block
+/// naming sender, date, subject and recipients, then its text.
+pub fn forwarded(original: &Value, tz: &TimeZone) -> String {
+    let mut out = String::from("---------- Forwarded message ----------\n");
+    out.push_str(&format!("From: {}\n", sender_text(&original["from"])));
+    out.push_str(&format!("Date: {}\n", sent_date(original, tz)));
+    out.push_str(&format!("Subject: {}\n", original["subject"].as_str().unwrap_or_default()));
+    out.push_str(&format!("To: {}\n", sender_text(&original["to"])));
+    if original["cc"].as_array().is_some_and(|cc| !cc.is_empty()) {
+        out.push_str(&format!("Cc: {}\n", sender_text(&original["cc"])));
+    }
+    out.push('\n');
+    out.push_str(text_body(original).trim_end());
+    out.push('\n');
+    out
+}
+
+/// The plain-text body of an email fetched with its body values.
+pub fn text_body(email: &Value) -> String {
+    let text = email["textBody"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|part| part["partId"].as_str())
+        .filter_map(|part| email["bodyValues"][part]["value"].as_str())
+        .collect::<Vec<_>>()
+        .join("\n");
+    if text.is_empty() {
+        email["preview"].as_str().unwrap_or_default().to_string()
+    } else {
+        text
+    }
+}
+
+/// `inReplyTo` and `references` for a reply to `original`: its Message-ID,
+/// appended to its own references.
+pub fn threading(original: &Value) -> (Vec<String>, Vec<String>) {
+    let ids = |field: &str| -> Vec<String> {
+        original[field]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|id| id.as_str().map(String::from))
+            .collect()
+    };
+    let in_reply_to = ids("messageId");
+    let mut references = ids("references");
+    references.extend(in_reply_to.iter().cloned());
+    (in_reply_to, references)
+}
+
+/// Recipients for a reply to `original`: its Reply-To (or From), plus with
+/// `reply_all` everyone else it was addressed to as Cc. Addresses in `own`
+/// (the user's identities) are left out and duplicates dropped.
+pub fn recipients(original: &Value, own: &[String], reply_all: bool) -> (Vec<String>, Vec<String>) {
+    let addresses = |field: &str| -> Vec<String> {
+        original[field]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|a| a["email"].as_str().map(String::from))
+            .collect()
+    };
+    let mut seen: Vec<String> = own.iter().map(|a| a.to_ascii_lowercase()).collect();
+    let mut keep = |address: String| {
+        let key = address.to_ascii_lowercase();
+        let new = !seen.contains(&key);
+        seen.push(key);
+        new.then_some(address)
+    };
+
+    let mut sender = addresses("replyTo");
+    if sender.is_empty() {
+        sender = addresses("from");
+    }
+    let mut to: Vec<String> = sender.into_iter().filter_map(&mut keep).collect();
+    let mut others = addresses("to");
+    if to.is_empty() {
+        // Replying to a message the user sent: address its recipients again.
+        to = others.drain(..).filter_map(&mut keep).collect();
+    }
+    let cc = if reply_all {
+        others.into_iter().chain(addresses("cc")).filter_map(&mut keep).collect()
+    } else {
+        Vec::new()
+    };
+    (to, cc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn phrases() -> Phrases {
+        Phrases {
+            reply_prefix: "Re:".into(),
+            forward_prefix: "Fwd:".into(),
+            quote_header: "On {{date}}, {{sender}} wrote:".into(),
+        }
+    }
+
+    #[test]
+    fn prefixes_subjects_once() {
+        let phrases = phrases();
+        assert_eq!(phrases.reply_subject("Lunch"), "Re: Lunch");
+        assert_eq!(phrases.reply_subject("RE: Lunch"), "RE: Lunch");
+        assert_eq!(phrases.reply_subject("AW: Mittagessen"), "AW: Mittagessen");
+        assert_eq!(phrases.reply_subject("Re[2]: Lunch"), "Re[2]: Lunch");
+        assert_eq!(phrases.reply_subject("Agenda: Monday"), "Re: Agenda: Monday");
+        assert_eq!(phrases.forward_subject("Fw: Lunch"), "Fw: Lunch");
+        assert_eq!(phrases.forward_subject("Re: Lunch"), "Fwd: Re: Lunch");
+    }
+
+    #[test]
+    fn quotes_each_line() {
+        let quoted = quote("On Mon, Ann wrote:", "Hi,\n\n> earlier\nBye\n\n");
+        assert_eq!(quoted, "On Mon, Ann wrote:\n> Hi,\n>\n>> earlier\n> Bye\n");
+    }
+
+    #[test]
+    fn renders_the_quote_header_in_the_time_zone() {
+        let original = json!({
+            "sentAt": "2024-03-01T17:30:00Z",
+            "from": [{"name": "Ann", "email": "ann@example.com"}]
+        });
+        let tz = TimeZone::fixed(jiff::tz::offset(1));
+        let header = phrases().quote_header(&original, &tz);
+        assert!(header.starts_with("On Fri, 1 Mar 2024 at 18:30, "), "{header}");
+        assert!(header.ends_with(" wrote:"));
+    }
+
+    #[test]
+    fn threads_onto_the_original() {
+        let original = json!({"messageId": ["c@x"], "references": ["a@x", "b@x"]});
+        let (in_reply_to, references) = threading(&original);
+        assert_eq!(in_reply_to, ["c@x"]);
+        assert_eq!(references, ["a@x", "b@x", "c@x"]);
+    }
+
+    #[test]
+    fn picks_reply_recipients() {
+        let original = json!({
+            "from": [{"email": "ann@example.com"}],
+            "replyTo": [{"email": "list@example.com"}],
+            "to": [{"email": "Me@example.com"}, {"email": "bob@example.com"}],
+            "cc": [{"email": "list@example.com"}, {"email": "cy@example.com"}]
+        });
+        let own = ["me@example.com".to_string()];
+        assert_eq!(recipients(&original, &own, false), (vec!["list@example.com".into()], vec![]));
+        let (to, cc) = recipients(&original, &own, true);
+        assert_eq!(to, ["list@example.com"]);
+        assert_eq!(cc, ["bob@example.com", "cy@example.com"]);
+
+        // Replying to one's own sent message goes back to its recipients.
+        let sent = json!({
+            "from": [{"email": "me@example.com"}],
+            "to": [{"email": "bob@example.com"}]
+        });
+        assert_eq!(recipients(&sent, &own, false).0, ["bob@example.com"]);
+    }
+
+    #[test]
+    fn falls_back_to_the_preview_for_the_text_body() {
+        let email = json!({
+            "textBody": [{"partId": "1"}, {"partId": "2"}],
+            "bodyValues": {"1": {"value": "one"}, "2": {"value": "two"}}
+        });
+        assert_eq!(text_body(&email), "one\ntwo");
+        assert_eq!(text_body(&json!({"preview": "short"})), "short");
+    }
+}
@@ -0,0 +1,192 @@
+use anyhow::{Context, Result, bail};
+use rmcp::{Peer, RoleServer};
+use std::path::{Component, Path, PathBuf};
+
+/// Resolves `path` to a location inside one of the root directories the
+/// MCP client exposes, for tools that write files on the user's machine.
+/// Relative paths are taken from the first root.
+pub async fn resolve(peer: &Peer<RoleServer>, path: &str) -> Result<PathBuf> {
+    let roots = list(peer, "written").await?;
+    writable(&roots, path)
+}
+
+/// Resolves `path` to a directory inside the client's roots to write files
+/// into, creating it when missing; its parent must already exist. The
+/// directory is canonicalized once it exists, so a symlink in its place
+/// can't lead outside the roots.
+pub async fn resolve_dir(peer: &Peer<RoleServer>, path: &str) -> Result<PathBuf> {
+    let roots = list(peer, "written").await?;
+    let dir = writable(&roots, path)?;
+    std::fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    let canonical = std::fs::canonicalize(&dir)?;
+    check(&roots, &dir, &canonical)?;
+    Ok(canonical)
+}
+
+/// The location within `roots` to write `path` to. The parent directory
+/// must already exist; it is canonicalized so `..` and symlinks can't
+/// escape the roots. An existing target is canonicalized as well, so a
+/// symlink in its place can't redirect the write outside them.
+fn writable(roots: &[PathBuf], path: &str) -> Result<PathBuf> {
+    let path = absolute(roots, path);
+    let name = match path.components().next_back() {
+        Some(Component::Normal(name)) => name.to_owned(),
+        _ => bail!("{} does not name a file", path.display()),
+    };
+    let parent = path.parent().unwrap_or(Path::new("/"));
+    let parent = std::fs::canonicalize(parent)
+        .with_context(|| format!("directory {} does not exist", parent.display()))?;
+    check(roots, &path, &parent)?;
+
+    let target = parent.join(name);
+    if std::fs::symlink_metadata(&target).is_err() {
+        return Ok(target);
+    }
+    let target = std::fs::canonicalize(&target)
+        .with_context(|| format!("{} is a link to nowhere", path.display()))?;
+    check(roots, &path, &target)?;
+    Ok(target)
+}
+
+/// Resolves `path` to an existing file inside the client's roots, for
+/// tools that read local files. The whole path is canonicalized, so a
+/// symlink inside a root can't point outside it.
+pub async fn resolve_existing(peer: &Peer<RoleServer>, path: &str) -> Result<PathBuf> {
+    let roots = list(peer, "read").await?;
+    let path = absolute(&roots, path);
+    let file = std::fs::canonicalize(&path)
+        .with_context(|| format!("{} does not exist", path.display()))?;
+    check(&roots, &path, &file)?;
+    Ok(file)
+}
+
+/// The client's local roots, canonicalized; `verb` says what can't be done
+/// with files when there are none.
+async fn list(peer: &Peer<RoleServer>, verb: &str) -> Result<Vec<PathBuf>> {
+    let supported = peer
+        .peer_info()
+        .is_some_and(|info| info.capabilities.roots.is_some());
+    if !supported {
+        bail!("the MCP client does not expose any roots, so files cannot be {verb} locally");
+    }
+    let roots: Vec<PathBuf> = peer
+        .list_roots()
+        .await
+        .context("failed to list the client's roots")?
+        .roots
+        .iter()
+        .filter_map(|root| file_path(&root.uri))
+        .filter_map(|root| std::fs::canonicalize(root).ok())
+        .collect();
+    if roots.is_empty() {
+        bail!("the MCP client exposes no local directories as roots");
+    }
+    Ok(roots)
+}
+
+/// `path` made absolute against the first root.
+fn absolute(roots: &[PathBuf], path: &str) -> PathBuf {
+    let path = Path::new(path.trim());
+    if path.is_absolute() { path.to_path_buf() } else { roots[0].join(path) }
+}
+
+/// Fails unless `canonical` (the canonical form of `path` or its parent)
+/// lies within one of `roots`.
+fn check(roots: &[PathBuf], path: &Path, canonical: &Path) -> Result<()> {
+    if !roots.iter().any(|root| canonical.starts_with(root)) {
+        let allowed: Vec<String> = roots.iter().map(|r| r.display().to_string()).collect();
+        bail!(
+            "{} is outside the client's roots ({})",
+            path.display(),
+            allowed.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// The local path of a `file://` root URI, percent-decoded.
+fn file_path(uri: &str) -> Option<PathBuf> {
+    let rest = uri.strip_prefix("file://")?;
+    let rest = rest.strip_prefix("localhost").filter(|r| r.starts_with('/')).unwrap_or(rest);
+    let bytes = rest.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok());
+        match (bytes[i], hex.and_then(|h| u8::from_str_radix(h, 16).ok())) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok().map(PathBuf::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh, canonicalized scratch directory for one test.
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("roots-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::canonicalize(dir).unwrap()
+    }
+
+    #[test]
+    fn decodes_file_uris() {
+        assert_eq!(file_path("file:///home/a%20b/mail"), Some(PathBuf::from("/home/a b/mail")));
+        assert_eq!(file_path("file://localhost/srv/x"), Some(PathBuf::from("/srv/x")));
+        assert_eq!(file_path("file:///tmp/100%zz"), Some(PathBuf::from("/tmp/100%zz")));
+        assert_eq!(file_path("file:///tmp/%"), Some(PathBuf::from("/tmp/%")));
+        assert_eq!(file_path("file:///bad/%FF"), None);
+        assert_eq!(file_path("https://example.com/x"), None);
+    }
+
+    #[test]
+    fn resolves_relative_paths_against_the_first_root() {
+        let root = scratch("relative");
+        let roots = [root.clone()];
+        assert_eq!(writable(&roots, "out.eml").unwrap(), root.join("out.eml"));
+        assert_eq!(writable(&roots, " ./out.eml ").unwrap(), root.join("out.eml"));
+        assert!(writable(&roots, "missing/out.eml").is_err());
+        assert!(writable(&roots, "..").is_err());
+    }
+
+    #[test]
+    fn rejects_paths_outside_the_roots() {
+        let root = scratch("outside-root");
+        let other = scratch("outside-other");
+        let roots = [root.clone()];
+        assert!(writable(&roots, "../roots-escape.eml").is_err());
+        assert!(writable(&roots, other.join("x.eml").to_str().unwrap()).is_err());
+        assert!(check(&roots, &other, &other).is_err());
+        assert!(check(&roots, &root, &root.join("a/b")).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn follows_symlinks_only_within_the_roots() {
+        use std::os::unix::fs::symlink;
+        let root = scratch("links-root");
+        let other = scratch("links-other");
+        std::fs::write(other.join("secret"), "keep").unwrap();
+        symlink(other.join("secret"), root.join("escape.eml")).unwrap();
+        symlink(other.join("missing"), root.join("dangling.eml")).unwrap();
+        symlink(&other, root.join("elsewhere")).unwrap();
+        std::fs::write(root.join("real.eml"), "").unwrap();
+        symlink(root.join("real.eml"), root.join("alias.eml")).unwrap();
+        let roots = [root.clone()];
+
+        assert!(writable(&roots, "escape.eml").is_err());
+        assert!(writable(&roots, "dangling.eml").is_err());
+        assert!(writable(&roots, "elsewhere/x.eml").is_err());
+        assert_eq!(writable(&roots, "alias.eml").unwrap(), root.join("real.eml"));
+    }
+}
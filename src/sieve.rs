@@ -0,0 +1,210 @@
+/// Markers delimiting the block of the active Sieve script that this server
+/// owns. Everything outside them is left untouched.
+const BEGIN: &str = "# BEGIN mcp-server-stalwart managed senders (do not edit)";
+const END: &str = "# END mcp-server-stalwart managed senders";
+
+/// Sender lists stored in the managed block. Entries are either full
+/// addresses or `@domain` patterns.
+#[derive(Debug, Default)]
+pub struct SenderLists {
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+}
+
+impl SenderLists {
+    /// Reads the lists back from the managed block of `script`, if present.
+    pub fn parse(script: &str) -> Self {
+        let mut lists = Self::default();
+        let Some((start, end)) = managed_range(script) else {
+            return lists;
+        };
+        for line in script[start..end].lines() {
+            if let Some(entry) = line.strip_prefix("# allow: ") {
+                lists.allow.push(entry.trim().to_string());
+            } else if let Some(entry) = line.strip_prefix("# deny: ") {
+                lists.deny.push(entry.trim().to_string());
+            }
+        }
+        lists
+    }
+
+    /// Adds `entries` to the allowlist, removing them from the denylist.
+    pub fn allow(&mut self, entries: &[String]) {
+        for entry in entries.iter().map(|e| normalize(e)) {
+            self.deny.retain(|d| *d != entry);
+            if !self.allow.contains(&entry) {
+                self.allow.push(entry);
+            }
+        }
+    }
+
+    /// Adds `entries` to the denylist, removing them from the allowlist.
+    pub fn deny(&mut self, entries: &[String]) {
+        for entry in entries.iter().map(|e| normalize(e)) {
+            self.allow.retain(|a| *a != entry);
+            if !self.deny.contains(&entry) {
+                self.deny.push(entry);
+            }
+        }
+    }
+
+    /// Renders the managed block. Allowed senders are kept and stop further
+    /// processing; denied senders are filed into `junk_folder`.
+    fn render(&self, junk_folder: &str) -> String {
+        let mut out = vec![BEGIN.to_string(), "require \"fileinto\";".to_string()];
+        out.extend(self.allow.iter().map(|a| format!("# allow: {a}")));
+        out.extend(self.deny.iter().map(|d| format!("# deny: {d}")));
+
+        if let Some(test) = sender_test(&self.allow) {
+            out.push(format!("if {test} {{ keep; stop; }}"));
+        }
+        if let Some(test) = sender_test(&self.deny) {
+            out.push(format!(
+                "if {test} {{ fileinto \"{}\"; stop; }}",
+                escape(junk_folder)
+            ));
+        }
+
+        out.push(END.to_string());
+        out.join("\n") + "\n"
+    }
+}
+
+/// Returns `script` with its managed block replaced by (or, if absent,
+/// inserted as) the rendering of `lists`.
+pub fn apply(script: &str, lists: &SenderLists, junk_folder: &str) -> String {
+    let block = lists.render(junk_folder);
+
+    if let Some((start, end)) = managed_range(script) {
+        let end = script[end..]
+            .find('\n')
+            .map(|i| end + i + 1)
+            .unwrap_or(script.len());
+        return format!("{}{block}{}", &script[..start], &script[end..]);
+    }
+
+    // `require` must precede every other command, so the block goes right
+    // after the script's own leading requires and comments.
+    let insert_at = leading_preamble_len(script);
+    format!("{}{block}{}", &script[..insert_at], &script[insert_at..])
+}
+
+fn managed_range(script: &str) -> Option<(usize, usize)> {
+    let start = script.find(BEGIN)?;
+    let end = script[start..].find(END)? + start;
+    Some((start, end))
+}
+
+/// Byte length of the leading run of blank lines, comments and `require`
+/// statements (which may span several lines).
+fn leading_preamble_len(script: &str) -> usize {
+    let mut pos = 0;
+    let mut in_require = false;
+
+    for line in script.split_inclusive('\n') {
+        let trimmed = line.trim();
+        if in_require || trimmed.starts_with("require") {
+            in_require = !trimmed.ends_with(';');
+        } else if !(trimmed.is_empty() || trimmed.starts_with('#')) {
+            break;
+        }
+        pos += line.len();
+    }
+
+    pos
+}
+
+fn sender_test(entries: &[String]) -> Option<String> {
+    let (domains, addresses): (Vec<&String>, Vec<&String>) =
+        entries.iter().partition(|e| e.starts_with('@'));
+
+    let mut tests = Vec::new();
+    if !addresses.is_empty() {
+        tests.push(format!("address :is \"from\" {}", string_list(&addresses, "")));
+    }
+    if !domains.is_empty() {
+        tests.push(format!(
+            "address :domain :is \"from\" {}",
+            string_list(&domains, "@")
+        ));
+    }
+
+    match tests.len() {
+        0 => None,
+        1 => tests.pop(),
+        _ => Some(format!("anyof ({})", tests.join(", "))),
+    }
+}
+
+fn string_list(entries: &[&String], strip: &str) -> String {
+    let items: Vec<String> = entries
+        .iter()
+        .map(|e| format!("\"{}\"", escape(e.strip_prefix(strip).unwrap_or(e))))
+        .collect();
+    format!("[{}]", items.join(", "))
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn normalize(entry: &str) -> String {
+    entry.trim().to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn inserts_the_block_after_leading_requires() {
+        let script = "# my rules\nrequire [\"fileinto\",\n  \"vacation\"];\n\nif true { keep; }\n";
+        let mut lists = SenderLists::default();
+        lists.deny(&entries(&["Spam@Example.com", "@junk.test"]));
+        let updated = apply(script, &lists, "Junk");
+
+        let block_at = updated.find(BEGIN).unwrap();
+        assert!(updated[..block_at].ends_with("\"vacation\"];\n\n"));
+        assert!(updated.ends_with(&format!("{END}\nif true {{ keep; }}\n")));
+        assert!(updated.contains(
+            "if anyof (address :is \"from\" [\"spam@example.com\"], \
+             address :domain :is \"from\" [\"junk.test\"]) { fileinto \"Junk\"; stop; }"
+        ));
+    }
+
+    #[test]
+    fn replaces_an_existing_block_and_reads_it_back() {
+        let mut lists = SenderLists::default();
+        lists.allow(&entries(&["boss@example.com"]));
+        let script = apply("keep;\n", &lists, "Junk");
+
+        let mut lists = SenderLists::parse(&script);
+        assert_eq!(lists.allow, ["boss@example.com"]);
+        lists.deny(&entries(&["boss@example.com", "@ads.test"]));
+        let script = apply(&script, &lists, "Junk");
+
+        assert_eq!(script.matches(BEGIN).count(), 1);
+        assert!(script.ends_with("keep;\n"));
+        let lists = SenderLists::parse(&script);
+        assert!(lists.allow.is_empty());
+        assert_eq!(lists.deny, ["boss@example.com", "@ads.test"]);
+    }
+
+    #[test]
+    fn escapes_quotes_in_folder_names() {
+        let mut lists = SenderLists::default();
+        lists.deny(&entries(&["x@y.z"]));
+        let script = apply("", &lists, "Junk \"bulk\"");
+        assert!(script.contains("fileinto \"Junk \\\"bulk\\\"\";"));
+    }
+
+    #[test]
+    fn parses_scripts_without_a_block_as_empty() {
+        let lists = SenderLists::parse("require \"fileinto\";\n# allow: nobody@example.com\n");
+        assert!(lists.allow.is_empty() && lists.deny.is_empty());
+    }
+}
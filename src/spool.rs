@@ -0,0 +1,41 @@
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+/// Writes an oversized tool result to `dir` and returns the file path.
+pub fn write(dir: &Path, tool: &str, extension: &str, text: &str) -> std::io::Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+    let ts = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let seq = SEQUENCE.fetch_add(1, Ordering::Relaxed);
+    let path = dir.join(format!("{tool}-{ts}-{seq}.{extension}"));
+    std::fs::write(&path, text)?;
+    Ok(path)
+}
+
+/// One line per top-level field describing its shape, so the caller can
+/// decide whether to open the spooled file.
+pub fn summarize(value: &Value) -> String {
+    let describe = |v: &Value| match v {
+        Value::Array(items) => format!("{} items", items.len()),
+        Value::Object(map) => format!("object with {} fields", map.len()),
+        Value::String(s) if s.chars().count() > 80 => {
+            format!("\"{}…\"", s.chars().take(80).collect::<String>())
+        }
+        other => other.to_string(),
+    };
+
+    match value {
+        Value::Object(map) => map
+            .iter()
+            .map(|(k, v)| format!("- {k}: {}", describe(v)))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        other => format!("- {}", describe(other)),
+    }
+}
@@ -0,0 +1,144 @@
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::index::sender_text;
+
+/// An email as listed in search results and threads. This and the other
+/// types here are returned as MCP `structuredContent` next to the text
+/// output, matching the output schema the tool declares.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct EmailSummary {
+    /// Email ID, for get_emails and the other email tools.
+    pub id: String,
+    pub thread_id: Option<String>,
+    /// When the server received the message (RFC 3339, UTC).
+    pub received_at: Option<String>,
+    /// Sender as "Name <address>".
+    pub from: Option<String>,
+    pub subject: Option<String>,
+    /// Start of the body text.
+    pub preview: Option<String>,
+    /// Whether the message lacks the $seen keyword.
+    pub unread: Option<bool>,
+}
+
+impl EmailSummary {
+    pub fn from_email(email: &Value) -> Self {
+        let text = |key: &str| email[key].as_str().map(String::from);
+        let from = match &email["from"] {
+            Value::String(s) => Some(s.clone()),
+            Value::Array(_) => Some(sender_text(&email["from"])),
+            _ => None,
+        };
+        Self {
+            id: email["id"].as_str().unwrap_or_default().to_string(),
+            thread_id: text("threadId"),
+            received_at: text("receivedAt"),
+            from,
+            subject: text("subject"),
+            preview: text("preview"),
+            unread: email["keywords"].as_object().map(|k| !k.contains_key("$seen")),
+        }
+    }
+}
+
+/// A page of search results.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct EmailList {
+    /// Matches in result order. Fields other than `id` are null unless the
+    /// search fetched them (e.g. with format "summary").
+    pub emails: Vec<EmailSummary>,
+    /// Index of the first result within all matches.
+    pub position: Option<u64>,
+    /// Number of matches, when the server calculated it.
+    pub total: Option<u64>,
+    /// State to pass to search_changes.
+    pub query_state: Option<String>,
+    /// Whether another page of results follows.
+    pub has_more: bool,
+    /// Position of the next page, when there is one.
+    pub next_position: Option<u64>,
+    /// Pass back as `cursor` to fetch the next page.
+    pub cursor: Option<String>,
+}
+
+impl EmailList {
+    /// From an Email/query result, using its fetched `list` when present.
+    pub fn from_result(result: &Value) -> Self {
+        let emails = match result["list"].as_array() {
+            Some(list) => list.iter().map(EmailSummary::from_email).collect(),
+            None => result["ids"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|id| EmailSummary::from_email(&serde_json::json!({ "id": id })))
+                .collect(),
+        };
+        Self {
+            emails,
+            position: result["position"].as_u64(),
+            total: result["total"].as_u64(),
+            query_state: result["queryState"].as_str().map(String::from),
+            has_more: result["has_more"].as_bool().unwrap_or(false),
+            next_position: result["next_position"].as_u64(),
+            cursor: result["cursor"].as_str().map(String::from),
+        }
+    }
+}
+
+/// One conversation.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Thread {
+    pub thread_id: Option<String>,
+    /// The conversation's emails, oldest first.
+    pub emails: Vec<EmailSummary>,
+}
+
+impl Thread {
+    /// From an Email/get result holding every email of one thread.
+    pub fn from_result(result: &Value) -> Self {
+        let list = result["list"].as_array().map(Vec::as_slice).unwrap_or_default();
+        Self {
+            thread_id: list.first().and_then(|e| e["threadId"].as_str()).map(String::from),
+            emails: list.iter().map(EmailSummary::from_email).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Mailbox {
+    pub id: String,
+    pub name: String,
+    pub parent_id: Option<String>,
+    /// Special-use role such as "inbox", "sent" or "trash".
+    pub role: Option<String>,
+    pub total_emails: u64,
+    pub unread_emails: u64,
+}
+
+/// Every mailbox in the account.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct MailboxList {
+    pub mailboxes: Vec<Mailbox>,
+}
+
+impl MailboxList {
+    /// From a Mailbox/get result.
+    pub fn from_result(result: &Value) -> Self {
+        let mailboxes = result["list"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|m| Mailbox {
+                id: m["id"].as_str().unwrap_or_default().to_string(),
+                name: m["name"].as_str().unwrap_or_default().to_string(),
+                parent_id: m["parentId"].as_str().map(String::from),
+                role: m["role"].as_str().map(String::from),
+                total_emails: m["totalEmails"].as_u64().unwrap_or_default(),
+                unread_emails: m["unreadEmails"].as_u64().unwrap_or_default(),
+            })
+            .collect();
+        Self { mailboxes }
+    }
+}
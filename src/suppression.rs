@@ -0,0 +1,88 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Why an address is suppressed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub status: String,
+    pub diagnostic: Option<String>,
+    /// When the bounce was received.
+    pub bounced_at: Option<String>,
+}
+
+/// Addresses that hard-bounced and must not be mailed again. Persisted as
+/// JSON at `SUPPRESSION_LIST_PATH`; without it the list lasts for the
+/// session only.
+pub struct SuppressionList {
+    path: Option<PathBuf>,
+    entries: Mutex<BTreeMap<String, Entry>>,
+}
+
+impl SuppressionList {
+    pub fn from_env() -> Result<Self> {
+        let path = std::env::var("SUPPRESSION_LIST_PATH").ok().map(PathBuf::from);
+        let entries = match &path {
+            Some(path) if path.exists() => {
+                let text = std::fs::read_to_string(path)
+                    .with_context(|| format!("failed to read {}", path.display()))?;
+                serde_json::from_str(&text)
+                    .with_context(|| format!("invalid suppression list {}", path.display()))?
+            }
+            _ => BTreeMap::new(),
+        };
+        Ok(Self {
+            path,
+            entries: Mutex::new(entries),
+        })
+    }
+
+    /// The suppressed addresses among `recipients`.
+    pub fn matching<'a>(&self, recipients: impl Iterator<Item = &'a String>) -> Vec<&'a str> {
+        let entries = self.entries.lock().unwrap();
+        recipients
+            .map(String::as_str)
+            .filter(|r| entries.contains_key(&r.trim().to_ascii_lowercase()))
+            .collect()
+    }
+
+    /// Adds or refreshes entries; returns the addresses that were new.
+    pub fn add(&self, additions: Vec<(String, Entry)>) -> Result<Vec<String>> {
+        let mut entries = self.entries.lock().unwrap();
+        let mut added = Vec::new();
+        for (address, entry) in additions {
+            let address = address.trim().to_ascii_lowercase();
+            if entries.insert(address.clone(), entry).is_none() {
+                added.push(address);
+            }
+        }
+        self.save(&entries)?;
+        Ok(added)
+    }
+
+    /// Removes addresses; returns those that were on the list.
+    pub fn remove(&self, addresses: &[String]) -> Result<Vec<String>> {
+        let mut entries = self.entries.lock().unwrap();
+        let removed: Vec<String> = addresses
+            .iter()
+            .map(|a| a.trim().to_ascii_lowercase())
+            .filter(|a| entries.remove(a).is_some())
+            .collect();
+        self.save(&entries)?;
+        Ok(removed)
+    }
+
+    pub fn count(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    fn save(&self, entries: &BTreeMap<String, Entry>) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let text = serde_json::to_string_pretty(entries)?;
+        std::fs::write(path, text).with_context(|| format!("failed to write {}", path.display()))
+    }
+}
@@ -0,0 +1,77 @@
+use anyhow::{Result, bail};
+use std::collections::BTreeMap;
+
+/// Replaces `{{name}}` placeholders (spaces inside the braces allowed) with
+/// values from `variables`. Fails, naming them, if any placeholder is left
+/// without a value, so a message never goes out with a literal `{{name}}`.
+pub fn render(text: &str, variables: &BTreeMap<String, String>) -> Result<String> {
+    let mut out = String::with_capacity(text.len());
+    let mut missing: Vec<&str> = Vec::new();
+    let mut rest = text;
+
+    while let Some(open) = rest.find("{{") {
+        let Some(close) = rest[open + 2..].find("}}").map(|i| open + 2 + i) else {
+            break;
+        };
+        out.push_str(&rest[..open]);
+        let name = rest[open + 2..close].trim();
+
+        match variables.get(name) {
+            Some(value) if is_name(name) => out.push_str(value),
+            _ => {
+                if is_name(name) && !missing.contains(&name) {
+                    missing.push(name);
+                }
+                out.push_str(&rest[open..close + 2]);
+            }
+        }
+        rest = &rest[close + 2..];
+    }
+    out.push_str(rest);
+
+    if !missing.is_empty() {
+        let names: Vec<String> = missing.iter().map(|n| format!("{{{{{n}}}}}")).collect();
+        bail!("unresolved placeholders: {}", names.join(", "));
+    }
+    Ok(out)
+}
+
+/// Placeholder names are identifiers, which keeps unrelated `{{...}}` text
+/// such as code samples out of the check.
+fn is_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '.' | '-'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn fills_placeholders_with_or_without_spaces() {
+        let variables = vars(&[("name", "Ada"), ("order.id", "42")]);
+        assert_eq!(
+            render("Hi {{name}}, order {{ order.id }} for {{name}}", &variables).unwrap(),
+            "Hi Ada, order 42 for Ada"
+        );
+    }
+
+    #[test]
+    fn names_every_missing_placeholder_once() {
+        let error = render("{{a}} {{b}} {{a}}", &vars(&[])).unwrap_err();
+        assert_eq!(error.to_string(), "unresolved placeholders: {{a}}, {{b}}");
+    }
+
+    #[test]
+    fn leaves_non_identifier_braces_alone() {
+        let text = "fn x() {{ y }} {{}} {{ a b }} {{ unclosed";
+        assert_eq!(render(text, &vars(&[("y", "no")])).unwrap(), "fn x() no {{}} {{ a b }} {{ unclosed");
+        assert_eq!(render("{{ not a name! }}", &vars(&[])).unwrap(), "{{ not a name! }}");
+    }
+}
@@ -0,0 +1,388 @@
+use anyhow::{Result, bail};
+
+/// MIME type Outlook uses for `winmail.dat` parts.
+pub const CONTENT_TYPE: &str = "application/ms-tnef";
+
+const SIGNATURE: u32 = 0x223E_9F78;
+const LEVEL_ATTACHMENT: u8 = 0x02;
+
+const ATT_ATTACH_REND_DATA: u32 = 0x0006_9002;
+const ATT_ATTACH_TITLE: u32 = 0x0001_8010;
+const ATT_ATTACH_DATA: u32 = 0x0006_800F;
+const ATT_ATTACHMENT: u32 = 0x0006_9005;
+
+const PR_ATTACH_LONG_FILENAME: u16 = 0x3707;
+const PR_ATTACH_MIME_TAG: u16 = 0x370E;
+
+/// A file carried inside a TNEF stream.
+#[derive(Debug, Default)]
+pub struct Attachment {
+    pub name: String,
+    pub content_type: Option<String>,
+    pub data: Vec<u8>,
+}
+
+/// Files inside a container get blob IDs of the form
+/// `tnef:<index>:<container blobId>`. They aren't server blobs: the file is
+/// decoded from the container whenever it is downloaded.
+const BLOB_PREFIX: &str = "tnef:";
+
+/// The blob ID for the `index`th file [`decode`] finds in `container`.
+pub fn blob_id(container: &str, index: usize) -> String {
+    format!("{BLOB_PREFIX}{index}:{container}")
+}
+
+/// The container blob ID and file index of a [`blob_id`], or `None` for a
+/// server blob ID.
+pub fn split_blob_id(blob_id: &str) -> Option<(&str, usize)> {
+    let (index, container) = blob_id.strip_prefix(BLOB_PREFIX)?.split_once(':')?;
+    Some((container, index.parse().ok()?))
+}
+
+/// True when an attachment descriptor from Email/get is a TNEF container.
+pub fn is_tnef(content_type: &str, name: &str) -> bool {
+    content_type.eq_ignore_ascii_case(CONTENT_TYPE) || name.eq_ignore_ascii_case("winmail.dat")
+}
+
+/// Extracts the real attachments from a TNEF (`winmail.dat`) stream.
+/// The message body and other MAPI properties are ignored.
+pub fn decode(data: &[u8]) -> Result<Vec<Attachment>> {
+    let mut r = Reader { data, pos: 0 };
+    if r.u32()? != SIGNATURE {
+        bail!("not a TNEF stream");
+    }
+    r.skip(2)?; // legacy key
+
+    let mut attachments: Vec<Attachment> = Vec::new();
+    while r.remaining() > 0 {
+        let level = r.u8()?;
+        let id = r.u32()?;
+        let len = r.u32()? as usize;
+        let value = r.take(len)?;
+        r.skip(2)?; // checksum
+
+        if level != LEVEL_ATTACHMENT {
+            continue;
+        }
+        match id {
+            // Each attachment's attributes open with its rendering data.
+            ATT_ATTACH_REND_DATA => attachments.push(Attachment::default()),
+            ATT_ATTACH_TITLE => {
+                if let Some(a) = attachments.last_mut()
+                    && a.name.is_empty()
+                {
+                    a.name = string8(value);
+                }
+            }
+            ATT_ATTACH_DATA => {
+                if let Some(a) = attachments.last_mut() {
+                    a.data = value.to_vec();
+                }
+            }
+            ATT_ATTACHMENT => {
+                if let Some(a) = attachments.last_mut() {
+                    // Best effort: the short title above remains if the
+                    // property block can't be read.
+                    let _ = apply_mapi_props(value, a);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    attachments.retain(|a| !a.data.is_empty());
+    for (i, a) in attachments.iter_mut().enumerate() {
+        if a.name.is_empty() {
+            a.name = format!("attachment-{}", i + 1);
+        }
+    }
+    Ok(attachments)
+}
+
+/// Reads the long filename and MIME tag from an attachment's MAPI
+/// property block.
+fn apply_mapi_props(data: &[u8], attachment: &mut Attachment) -> Result<()> {
+    let mut r = Reader { data, pos: 0 };
+    let count = r.u32()?;
+
+    for _ in 0..count {
+        let kind = r.u16()?;
+        let id = r.u16()?;
+        if id >= 0x8000 {
+            // Named property: GUID, then a numeric ID or a padded UTF-16 name.
+            r.skip(16)?;
+            if r.u32()? == 0 {
+                r.skip(4)?;
+            } else {
+                let len = r.u32()? as usize;
+                r.skip(padded(len))?;
+            }
+        }
+
+        let multi = kind & 0x1000 != 0;
+        let base = kind & 0x0FFF;
+        let values = if multi || is_variable(base) { r.u32()? } else { 1 };
+
+        for _ in 0..values {
+            if is_variable(base) {
+                let len = r.u32()? as usize;
+                let value = r.take(len)?;
+                r.skip(padded(len) - len)?;
+                match (id, base) {
+                    (PR_ATTACH_LONG_FILENAME | PR_ATTACH_MIME_TAG, 0x001E) => {
+                        set_prop(attachment, id, string8(value))
+                    }
+                    (PR_ATTACH_LONG_FILENAME | PR_ATTACH_MIME_TAG, 0x001F) => {
+                        set_prop(attachment, id, utf16(value))
+                    }
+                    _ => {}
+                }
+            } else {
+                let Some(size) = fixed_size(base) else {
+                    bail!("unsupported MAPI property type {base:#06x}");
+                };
+                r.skip(size)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn set_prop(attachment: &mut Attachment, id: u16, value: String) {
+    if value.is_empty() {
+        return;
+    }
+    match id {
+        PR_ATTACH_LONG_FILENAME => attachment.name = value,
+        _ => attachment.content_type = Some(value),
+    }
+}
+
+/// String, binary and embedded-object types carry a length per value.
+fn is_variable(kind: u16) -> bool {
+    matches!(kind, 0x001E | 0x001F | 0x0102 | 0x000D)
+}
+
+fn fixed_size(kind: u16) -> Option<usize> {
+    match kind {
+        0x0001 | 0x0002 | 0x0003 | 0x0004 | 0x000A | 0x000B => Some(4),
+        0x0005 | 0x0006 | 0x0007 | 0x0014 | 0x0040 => Some(8),
+        0x0048 => Some(16),
+        _ => None,
+    }
+}
+
+fn padded(len: usize) -> usize {
+    len.div_ceil(4) * 4
+}
+
+fn string8(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).trim().to_string()
+}
+
+fn utf16(bytes: &[u8]) -> String {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .take_while(|&u| u != 0)
+        .collect();
+    String::from_utf16_lossy(&units).trim().to_string()
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if len > self.remaining() {
+            bail!("truncated TNEF stream");
+        }
+        let slice = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(slice)
+    }
+
+    fn skip(&mut self, len: usize) -> Result<()> {
+        self.take(len).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let b = self.take(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One TNEF attribute: level, ID, length, value and a checksum (which
+    /// `decode` doesn't verify).
+    fn attribute(level: u8, id: u32, value: &[u8]) -> Vec<u8> {
+        let mut out = vec![level];
+        out.extend(id.to_le_bytes());
+        out.extend((value.len() as u32).to_le_bytes());
+        out.extend(value);
+        out.extend([0, 0]);
+        out
+    }
+
+    fn stream(attributes: &[Vec<u8>]) -> Vec<u8> {
+        let mut out = SIGNATURE.to_le_bytes().to_vec();
+        out.extend([0x01, 0x00]);
+        for a in attributes {
+            out.extend(a);
+        }
+        out
+    }
+
+    fn variable_prop(kind: u16, id: u16, value: &[u8]) -> Vec<u8> {
+        let mut out = kind.to_le_bytes().to_vec();
+        out.extend(id.to_le_bytes());
+        out.extend(1u32.to_le_bytes());
+        out.extend((value.len() as u32).to_le_bytes());
+        out.extend(value);
+        out.resize(out.len() + padded(value.len()) - value.len(), 0);
+        out
+    }
+
+    fn props(count: u32, props: &[Vec<u8>]) -> Vec<u8> {
+        let mut out = count.to_le_bytes().to_vec();
+        for p in props {
+            out.extend(p);
+        }
+        out
+    }
+
+    fn utf16_bytes(s: &str) -> Vec<u8> {
+        s.encode_utf16().chain([0]).flat_map(u16::to_le_bytes).collect()
+    }
+
+    #[test]
+    fn extracts_attachments_with_title_and_long_filename() {
+        let long_name = utf16_bytes("Report Q3.pdf");
+        let data = stream(&[
+            attribute(0x01, 0x0008_8009, b"IPM.Note\0"),
+            attribute(LEVEL_ATTACHMENT, ATT_ATTACH_REND_DATA, &[0; 14]),
+            attribute(LEVEL_ATTACHMENT, ATT_ATTACH_TITLE, b"REPORT~1.PDF\0"),
+            attribute(LEVEL_ATTACHMENT, ATT_ATTACH_DATA, b"%PDF-1.4"),
+            attribute(
+                LEVEL_ATTACHMENT,
+                ATT_ATTACHMENT,
+                &props(
+                    2,
+                    &[
+                        variable_prop(0x001F, PR_ATTACH_LONG_FILENAME, &long_name),
+                        variable_prop(0x001E, PR_ATTACH_MIME_TAG, b"application/pdf\0"),
+                    ],
+                ),
+            ),
+            attribute(LEVEL_ATTACHMENT, ATT_ATTACH_REND_DATA, &[0; 14]),
+            attribute(LEVEL_ATTACHMENT, ATT_ATTACH_TITLE, b"notes.txt\0"),
+            attribute(LEVEL_ATTACHMENT, ATT_ATTACH_DATA, b"hello"),
+        ]);
+        let files = decode(&data).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].name, "Report Q3.pdf");
+        assert_eq!(files[0].content_type.as_deref(), Some("application/pdf"));
+        assert_eq!(files[0].data, b"%PDF-1.4");
+        assert_eq!(files[1].name, "notes.txt");
+        assert_eq!(files[1].content_type, None);
+        assert_eq!(files[1].data, b"hello");
+    }
+
+    #[test]
+    fn skips_multi_valued_properties() {
+        let mut multi_long = 0x1003u16.to_le_bytes().to_vec();
+        multi_long.extend(0x0E21u16.to_le_bytes());
+        multi_long.extend(2u32.to_le_bytes());
+        multi_long.extend(7u32.to_le_bytes());
+        multi_long.extend(9u32.to_le_bytes());
+
+        let mut multi_string = 0x101Eu16.to_le_bytes().to_vec();
+        multi_string.extend(0x3001u16.to_le_bytes());
+        multi_string.extend(2u32.to_le_bytes());
+        for value in [&b"ab\0"[..], b"cdefg\0"] {
+            multi_string.extend((value.len() as u32).to_le_bytes());
+            multi_string.extend(value);
+            multi_string.resize(multi_string.len() + padded(value.len()) - value.len(), 0);
+        }
+
+        let filename = variable_prop(0x001E, PR_ATTACH_LONG_FILENAME, b"budget.xlsx\0");
+        let data = stream(&[
+            attribute(LEVEL_ATTACHMENT, ATT_ATTACH_REND_DATA, &[0; 14]),
+            attribute(LEVEL_ATTACHMENT, ATT_ATTACH_DATA, b"PK"),
+            attribute(
+                LEVEL_ATTACHMENT,
+                ATT_ATTACHMENT,
+                &props(3, &[multi_long, multi_string, filename]),
+            ),
+        ]);
+        let files = decode(&data).unwrap();
+        assert_eq!(files[0].name, "budget.xlsx");
+    }
+
+    #[test]
+    fn names_untitled_files_by_position() {
+        let data = stream(&[
+            attribute(LEVEL_ATTACHMENT, ATT_ATTACH_REND_DATA, &[0; 14]),
+            attribute(LEVEL_ATTACHMENT, ATT_ATTACH_TITLE, b"kept.txt\0"),
+            attribute(LEVEL_ATTACHMENT, ATT_ATTACH_DATA, b"a"),
+            // No data: dropped, and not counted when numbering.
+            attribute(LEVEL_ATTACHMENT, ATT_ATTACH_REND_DATA, &[0; 14]),
+            attribute(LEVEL_ATTACHMENT, ATT_ATTACH_REND_DATA, &[0; 14]),
+            attribute(LEVEL_ATTACHMENT, ATT_ATTACH_DATA, b"b"),
+        ]);
+        let names: Vec<String> = decode(&data).unwrap().into_iter().map(|f| f.name).collect();
+        assert_eq!(names, ["kept.txt", "attachment-2"]);
+    }
+
+    #[test]
+    fn keeps_the_title_when_properties_are_unreadable() {
+        let data = stream(&[
+            attribute(LEVEL_ATTACHMENT, ATT_ATTACH_REND_DATA, &[0; 14]),
+            attribute(LEVEL_ATTACHMENT, ATT_ATTACH_TITLE, b"short.doc\0"),
+            attribute(LEVEL_ATTACHMENT, ATT_ATTACH_DATA, b"x"),
+            attribute(LEVEL_ATTACHMENT, ATT_ATTACHMENT, &props(5, &[])),
+        ]);
+        assert_eq!(decode(&data).unwrap()[0].name, "short.doc");
+    }
+
+    #[test]
+    fn rejects_truncated_and_foreign_streams() {
+        let mut data = stream(&[
+            attribute(LEVEL_ATTACHMENT, ATT_ATTACH_REND_DATA, &[0; 14]),
+            attribute(LEVEL_ATTACHMENT, ATT_ATTACH_DATA, b"0123456789"),
+        ]);
+        data.truncate(data.len() - 6);
+        let err = decode(&data).unwrap_err();
+        assert_eq!(err.to_string(), "truncated TNEF stream");
+
+        assert_eq!(decode(b"PK\x03\x04zipfile").unwrap_err().to_string(), "not a TNEF stream");
+        assert!(decode(&SIGNATURE.to_le_bytes()).is_err());
+    }
+
+    #[test]
+    fn blob_ids_round_trip() {
+        let id = blob_id("Gabc123", 2);
+        assert_eq!(split_blob_id(&id), Some(("Gabc123", 2)));
+        assert_eq!(split_blob_id("Gabc123"), None);
+        assert_eq!(split_blob_id("tnef:x:Gabc123"), None);
+    }
+}
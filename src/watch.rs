@@ -0,0 +1,162 @@
+use anyhow::Result;
+use rmcp::model::ResourceUpdatedNotificationParam;
+use rmcp::{Peer, RoleServer};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+use crate::jmap::JmapClient;
+use crate::push::Push;
+
+/// URI prefix of the mailbox resources, followed by the mailbox ID.
+pub const MAILBOX_URI_PREFIX: &str = "jmap://mailbox/";
+
+#[derive(Default)]
+struct State {
+    uris: HashSet<String>,
+    polling: bool,
+}
+
+/// Mailbox resources the client has subscribed to. While any are
+/// subscribed, a background task checks Mailbox/changes and sends
+/// `notifications/resources/updated` for each subscribed mailbox whose
+/// contents or counts changed. Checks follow the server's EventSource push
+/// when it has one, and run on a fixed interval while it doesn't or while
+/// the push stream is down.
+#[derive(Default)]
+pub struct Subscriptions {
+    state: Mutex<State>,
+    push: Push,
+}
+
+impl Subscriptions {
+    /// Adds `uri`, starting the poller if it isn't already running. The
+    /// poller always uses the session's current client, so it follows
+    /// `use_account`.
+    pub fn subscribe(
+        self: &Arc<Self>,
+        uri: String,
+        peer: Peer<RoleServer>,
+        client: Arc<RwLock<Arc<JmapClient>>>,
+        interval: Duration,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        state.uris.insert(uri);
+        if !state.polling {
+            state.polling = true;
+            let this = self.clone();
+            tokio::spawn(async move { this.poll(peer, client, interval).await });
+        }
+    }
+
+    /// Removes `uri`; the poller stops at its next wake-up if it was the
+    /// last one.
+    pub fn unsubscribe(&self, uri: &str) {
+        self.state.lock().unwrap().uris.remove(uri);
+        self.push.wake();
+    }
+
+    fn subscribed(&self) -> HashSet<String> {
+        self.state.lock().unwrap().uris.clone()
+    }
+
+    /// Polls until the last subscription is removed or the client goes away,
+    /// with the push listener running alongside.
+    async fn poll(
+        self: Arc<Self>,
+        peer: Peer<RoleServer>,
+        client: Arc<RwLock<Arc<JmapClient>>>,
+        interval: Duration,
+    ) {
+        let stop = CancellationToken::new();
+        let _stop_push = stop.clone().drop_guard();
+        {
+            let this = self.clone();
+            let client = client.clone();
+            tokio::spawn(async move { this.push.listen(client, stop).await });
+        }
+
+        // (account, Mailbox state) as of the last successful check.
+        let mut since: Option<(String, String)> = None;
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.uris.is_empty() {
+                    state.polling = false;
+                    return;
+                }
+            }
+            let current = client.read().unwrap().clone();
+            match self.check(&current, since.clone()).await {
+                Ok((state, changed)) => {
+                    since = Some(state);
+                    for uri in changed {
+                        let param = ResourceUpdatedNotificationParam { uri };
+                        if let Err(e) = peer.notify_resource_updated(param).await {
+                            tracing::debug!("failed to send resource update, stopping: {e}");
+                            let mut state = self.state.lock().unwrap();
+                            state.uris.clear();
+                            state.polling = false;
+                            return;
+                        }
+                    }
+                }
+                Err(e) => tracing::warn!("mailbox change poll failed: {e:#}"),
+            }
+            if self.push.is_connected() {
+                self.push.changed().await;
+            } else {
+                tokio::select! {
+                    _ = tokio::time::sleep(interval) => {}
+                    _ = self.push.changed() => {}
+                }
+            }
+        }
+    }
+
+    /// The new (account, state) and the subscribed URIs that changed since
+    /// `since`. Every subscription counts as changed when the changes can't
+    /// be calculated or the account was switched.
+    async fn check(
+        &self,
+        client: &JmapClient,
+        since: Option<(String, String)>,
+    ) -> Result<((String, String), Vec<String>)> {
+        let account = client.account_id().to_string();
+        let subscribed = self.subscribed();
+        let everything = || subscribed.iter().cloned().collect();
+
+        let Some((previous, mut state)) = since else {
+            return Ok(((account, client.mailbox_state().await?), Vec::new()));
+        };
+        if previous != account {
+            return Ok(((account, client.mailbox_state().await?), everything()));
+        }
+
+        let mut changed = HashSet::new();
+        loop {
+            let changes = match client.mailbox_changes(&state).await {
+                Ok(changes) => changes,
+                Err(e) if e.to_string().contains("cannotCalculateChanges") => {
+                    return Ok(((account, client.mailbox_state().await?), everything()));
+                }
+                Err(e) => return Err(e),
+            };
+            for key in ["created", "updated", "destroyed"] {
+                for id in changes[key].as_array().into_iter().flatten().filter_map(|v| v.as_str()) {
+                    changed.insert(format!("{MAILBOX_URI_PREFIX}{id}"));
+                }
+            }
+            let Some(new_state) = changes["newState"].as_str() else {
+                break;
+            };
+            state = new_state.to_string();
+            if changes["hasMoreChanges"] != true {
+                break;
+            }
+        }
+        let changed = subscribed.into_iter().filter(|uri| changed.contains(uri)).collect();
+        Ok(((account, state), changed))
+    }
+}
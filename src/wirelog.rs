@@ -0,0 +1,106 @@
+use anyhow::{Context, Result};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::debug::redact;
+
+/// Default size at which the log file is rotated.
+const DEFAULT_MAX_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Number of rotated files kept alongside the active one (`.1` … `.N`).
+const KEEP_ROTATED: usize = 5;
+
+/// Appends raw JMAP requests and responses to a size-rotated file.
+///
+/// The Authorization header is written as a fixed placeholder and every entry
+/// goes through [`redact`], so credentials never reach the log.
+pub struct WireLog {
+    path: PathBuf,
+    max_bytes: u64,
+    enabled: AtomicBool,
+    file: Mutex<Option<File>>,
+}
+
+impl WireLog {
+    /// Builds a wire log from `JMAP_LOG_FILE` / `JMAP_LOG_MAX_BYTES`, or
+    /// returns `None` when no log file is configured.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(path) = std::env::var("JMAP_LOG_FILE") else {
+            return Ok(None);
+        };
+        let max_bytes = match std::env::var("JMAP_LOG_MAX_BYTES") {
+            Ok(v) => v.parse().context("JMAP_LOG_MAX_BYTES must be a number")?,
+            Err(_) => DEFAULT_MAX_BYTES,
+        };
+        Ok(Some(Self {
+            path: PathBuf::from(path),
+            max_bytes,
+            enabled: AtomicBool::new(true),
+            file: Mutex::new(None),
+        }))
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn request(&self, method: &str, url: &str, body: &str) {
+        self.write(&format!(
+            "> {method} {url}\n> Authorization: [redacted]\n{body}\n"
+        ));
+    }
+
+    pub fn response(&self, status: u16, body: &str) {
+        self.write(&format!("< {status}\n{body}\n"));
+    }
+
+    fn write(&self, entry: &str) {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return;
+        }
+        // Logging must never take down a tool call, so I/O errors are dropped.
+        let _ = self.try_write(&redact(entry));
+    }
+
+    fn try_write(&self, entry: &str) -> std::io::Result<()> {
+        let mut guard = self.file.lock().unwrap();
+
+        let len = match guard.as_ref() {
+            Some(f) => f.metadata()?.len(),
+            None => std::fs::metadata(&self.path).map(|m| m.len()).unwrap_or(0),
+        };
+        if len > 0 && len + entry.len() as u64 > self.max_bytes {
+            *guard = None;
+            self.rotate()?;
+        }
+
+        if guard.is_none() {
+            *guard = Some(OpenOptions::new().create(true).append(true).open(&self.path)?);
+        }
+        let file = guard.as_mut().unwrap();
+
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        writeln!(file, "--- {ts}")?;
+        file.write_all(entry.as_bytes())
+    }
+
+    fn rotate(&self) -> std::io::Result<()> {
+        let rotated = |n: usize| {
+            let mut name = self.path.clone().into_os_string();
+            name.push(format!(".{n}"));
+            PathBuf::from(name)
+        };
+        let _ = std::fs::remove_file(rotated(KEEP_ROTATED));
+        for n in (1..KEEP_ROTATED).rev() {
+            let _ = std::fs::rename(rotated(n), rotated(n + 1));
+        }
+        std::fs::rename(&self.path, rotated(1))
+    }
+}